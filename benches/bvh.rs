@@ -0,0 +1,64 @@
+//! Closest-hit queries for the primary rays of the random scene, against a linear list of its
+//! objects and against the BVH built from them. Run with `cargo bench --bench bvh`.
+
+use std::time::{Duration, Instant};
+use wave_tracer::random_scene::random_scene;
+use wave_tracer::sampler::IndependentSampler;
+use wave_tracer::{bvh, Camera, CameraSettings, Hittable};
+
+// The BVH is expected to be at least this many times faster than the list.
+const MIN_SPEEDUP: f64 = 5.0;
+
+// Best time of a few runs, and the number of rays that hit something.
+fn trace_primary_rays(world: &dyn Hittable) -> (Duration, usize) {
+    let camera = CameraSettings::default();
+    let cam = Camera::new(
+        camera.look_from,
+        camera.look_at,
+        camera.vup,
+        camera.vfov,
+        1.5,
+        0.0,
+        camera.focus_dist,
+    );
+    let (width, height) = (600, 400);
+    (0..3)
+        .map(|_| {
+            let mut sampler = IndependentSampler::new(0, (0, 0), 0);
+            let start = Instant::now();
+            let hits = (0..height)
+                .flat_map(|j| (0..width).map(move |i| (i, j)))
+                .filter(|&(i, j)| {
+                    let r = cam.get_ray(
+                        i as f64 / width as f64,
+                        j as f64 / height as f64,
+                        &mut sampler,
+                    );
+                    world.hit(&r, 0.001, f64::INFINITY).is_some()
+                })
+                .count();
+            (start.elapsed(), hits)
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let list = random_scene(1);
+    let objects = list.objects.len();
+    let bvh = bvh::build(random_scene(1).objects);
+
+    let (list_time, list_hits) = trace_primary_rays(&list);
+    let (bvh_time, bvh_hits) = trace_primary_rays(&*bvh);
+    assert_eq!(list_hits, bvh_hits);
+    let speedup = list_time.as_secs_f64() / bvh_time.as_secs_f64();
+    println!(
+        "{} objects: list {:?}, bvh {:?}, {:.1}x faster",
+        objects, list_time, bvh_time, speedup
+    );
+    assert!(
+        speedup >= MIN_SPEEDUP,
+        "the BVH should be at least {}x faster than the list",
+        MIN_SPEEDUP
+    );
+}
@@ -0,0 +1,100 @@
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+
+/// Axis-aligned bounding box.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub minimum: Point3,
+    pub maximum: Point3,
+}
+
+impl Aabb {
+    pub fn new(minimum: Point3, maximum: Point3) -> Self {
+        Self { minimum, maximum }
+    }
+
+    /// Slab test. Conservative, so it never rejects a ray that hits the contents.
+    pub fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        // Widen the far distance to cover rounding errors, see PBRT 3.9.2.
+        const ROUNDING: f64 = 1.0 + 6.0 * f64::EPSILON;
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for axis in 0..3 {
+            let inv_d = 1.0 / r.direction[axis];
+            let mut t0 = (self.minimum[axis] - r.origin[axis]) * inv_d;
+            let mut t1 = (self.maximum[axis] - r.origin[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // `max` and `min` ignore the NaN of a ray lying exactly in a slab plane
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1 * ROUNDING);
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn surrounding_box(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            minimum: Point3::new(
+                self.minimum.x.min(other.minimum.x),
+                self.minimum.y.min(other.minimum.y),
+                self.minimum.z.min(other.minimum.z),
+            ),
+            maximum: Point3::new(
+                self.maximum.x.max(other.maximum.x),
+                self.maximum.y.max(other.maximum.y),
+                self.maximum.z.max(other.maximum.z),
+            ),
+        }
+    }
+
+    pub fn surrounding_point(&self, p: Point3) -> Aabb {
+        self.surrounding_box(&Aabb::new(p, p))
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.minimum + self.maximum)
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let Vec3 { x, y, z } = self.maximum - self.minimum;
+        2.0 * (x * y + y * z + z * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> Aabb {
+        Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn hits_box_in_front() {
+        let r = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(unit_box().hit(&r, 0.0, f64::INFINITY));
+        assert!(!unit_box().hit(&r, 0.0, 3.0));
+        assert!(!unit_box().hit(&r, 7.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn misses_box_to_the_side() {
+        let r = Ray::new(Point3::new(2.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(!unit_box().hit(&r, 0.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn ray_in_slab_plane() {
+        let r = Ray::new(Point3::new(1.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(unit_box().hit(&r, 0.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn surface_area() {
+        assert_eq!(unit_box().surface_area(), 24.0);
+    }
+}
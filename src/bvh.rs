@@ -0,0 +1,268 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::ray::Ray;
+use crate::vec3::Point3;
+
+// Number of buckets the centroids are sorted into when looking for a split.
+const BINS: usize = 16;
+// Nodes with at most this many objects become leaves when splitting does not pay off.
+const MAX_LEAF_SIZE: usize = 4;
+// Cost of visiting a node relative to intersecting one object.
+const TRAVERSAL_COST: f64 = 1.0;
+
+/// Bounding volume hierarchy node, split with the surface area heuristic.
+pub struct BvhNode {
+    left: Box<dyn Hittable>,
+    right: Box<dyn Hittable>,
+    bbox: Aabb,
+    axis: usize,
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return None;
+        }
+
+        // Visit the child nearer along the split axis first so that the far one can be culled.
+        let (near, far) = if r.direction[self.axis] < 0.0 {
+            (&self.right, &self.left)
+        } else {
+            (&self.left, &self.right)
+        };
+        let hit_near = near.hit(r, t_min, t_max);
+        let closest_so_far = hit_near.as_ref().map_or(t_max, |rec| rec.t);
+        far.hit(r, t_min, closest_so_far).or(hit_near)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
+
+/// Builds a hierarchy over `objects`. Objects without a bounding box are kept next to it and
+/// always tested.
+pub fn build(objects: Vec<Box<dyn Hittable>>) -> Box<dyn Hittable> {
+    let mut unbounded = Vec::new();
+    let mut primitives = Vec::new();
+    for object in objects {
+        match object.bounding_box() {
+            Some(bbox) => primitives.push(Primitive {
+                bbox,
+                centroid: bbox.centroid(),
+                object,
+            }),
+            None => unbounded.push(object),
+        }
+    }
+
+    if primitives.is_empty() {
+        return Box::new(HittableList { objects: unbounded });
+    }
+    let tree = build_node(primitives);
+    if unbounded.is_empty() {
+        tree
+    } else {
+        unbounded.push(tree);
+        Box::new(HittableList { objects: unbounded })
+    }
+}
+
+struct Primitive {
+    bbox: Aabb,
+    centroid: Point3,
+    object: Box<dyn Hittable>,
+}
+
+#[derive(Copy, Clone)]
+struct Bin {
+    bbox: Option<Aabb>,
+    count: usize,
+}
+
+impl Bin {
+    const EMPTY: Bin = Bin {
+        bbox: None,
+        count: 0,
+    };
+
+    fn add(&mut self, bbox: &Aabb, count: usize) {
+        self.bbox = Some(match self.bbox {
+            Some(b) => b.surrounding_box(bbox),
+            None => *bbox,
+        });
+        self.count += count;
+    }
+
+    fn cost(&self) -> f64 {
+        self.bbox.map_or(0.0, |b| b.surface_area()) * self.count as f64
+    }
+}
+
+fn build_node(mut primitives: Vec<Primitive>) -> Box<dyn Hittable> {
+    let n = primitives.len();
+    if n == 1 {
+        return primitives.pop().unwrap().object;
+    }
+
+    let first = primitives[0].bbox;
+    let bbox = primitives[1..]
+        .iter()
+        .fold(first, |b, p| b.surrounding_box(&p.bbox));
+    let centroids = primitives[1..]
+        .iter()
+        .fold(Aabb::new(first.centroid(), first.centroid()), |b, p| {
+            b.surrounding_point(p.centroid)
+        });
+
+    let bin_of = |axis: usize, p: &Primitive| {
+        let lo = centroids.minimum[axis];
+        let extent = centroids.maximum[axis] - lo;
+        (((p.centroid[axis] - lo) / extent * BINS as f64) as usize).min(BINS - 1)
+    };
+
+    // Cheapest split as (cost, axis, first bin on the right side).
+    let mut best: Option<(f64, usize, usize)> = None;
+    for axis in 0..3 {
+        if centroids.maximum[axis] <= centroids.minimum[axis] {
+            continue;
+        }
+        let mut bins = [Bin::EMPTY; BINS];
+        for p in &primitives {
+            bins[bin_of(axis, p)].add(&p.bbox, 1);
+        }
+
+        // Costs of everything right of each split, swept from the right.
+        let mut right_costs = [0.0; BINS];
+        let mut right = Bin::EMPTY;
+        for split in (1..BINS).rev() {
+            if let Some(b) = &bins[split].bbox {
+                right.add(b, bins[split].count);
+            }
+            right_costs[split] = right.cost();
+        }
+
+        let mut left = Bin::EMPTY;
+        for split in 1..BINS {
+            if let Some(b) = &bins[split - 1].bbox {
+                left.add(b, bins[split - 1].count);
+            }
+            if left.count == 0 || left.count == n {
+                continue;
+            }
+            let cost = TRAVERSAL_COST + (left.cost() + right_costs[split]) / bbox.surface_area();
+            if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                best = Some((cost, axis, split));
+            }
+        }
+    }
+
+    let (axis, right) = match best {
+        Some((cost, _, _)) if cost >= n as f64 && n <= MAX_LEAF_SIZE => return leaf(primitives),
+        Some((_, axis, split)) => {
+            let right = primitives
+                .iter()
+                .filter(|p| bin_of(axis, p) >= split)
+                .count();
+            // Stable partition, left side first
+            primitives.sort_by_key(|p| bin_of(axis, p) >= split);
+            let right = primitives.split_off(n - right);
+            (axis, right)
+        }
+        // All centroids coincide, so no split separates them.
+        None if n <= MAX_LEAF_SIZE => return leaf(primitives),
+        None => (0, primitives.split_off(n / 2)),
+    };
+
+    Box::new(BvhNode {
+        left: build_node(primitives),
+        right: build_node(right),
+        bbox,
+        axis,
+    })
+}
+
+fn leaf(primitives: Vec<Primitive>) -> Box<dyn Hittable> {
+    Box::new(HittableList {
+        objects: primitives.into_iter().map(|p| p.object).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::{Lambertian, Material};
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::sync::Arc;
+
+    fn spheres(count: usize) -> Vec<Box<dyn Hittable>> {
+        let mut rng = StdRng::seed_from_u64(7);
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        (0..count)
+            .map(|_| {
+                let center = Point3::new(
+                    rng.gen_range(-10.0..10.0),
+                    rng.gen_range(-10.0..10.0),
+                    rng.gen_range(-10.0..10.0),
+                );
+                Box::new(Sphere {
+                    center,
+                    radius: rng.gen_range(0.1..1.5),
+                    material: Arc::clone(&material),
+                }) as Box<dyn Hittable>
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_closest_hit_as_list() {
+        let list = HittableList {
+            objects: spheres(500),
+        };
+        let bvh = build(spheres(500));
+        assert_eq!(bvh.bounding_box(), list.bounding_box());
+
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut hits = 0;
+        for _ in 0..20_000 {
+            let origin = Point3::new(
+                rng.gen_range(-15.0..15.0),
+                rng.gen_range(-15.0..15.0),
+                rng.gen_range(-15.0..15.0),
+            );
+            let direction = Vec3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+            let r = Ray::new(origin, direction);
+            let expected = list.hit(&r, 0.001, f64::INFINITY);
+            let actual = bvh.hit(&r, 0.001, f64::INFINITY);
+            match (expected, actual) {
+                (Some(e), Some(a)) => {
+                    assert_eq!((e.t, e.p, e.normal), (a.t, a.p, a.normal));
+                    hits += 1;
+                }
+                (None, None) => {}
+                (e, a) => panic!(
+                    "list hit {:?}, bvh hit {:?}",
+                    e.map(|rec| rec.t),
+                    a.map(|rec| rec.t)
+                ),
+            }
+        }
+        assert!(hits > 1000);
+    }
+
+    #[test]
+    fn empty_and_single() {
+        let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(build(Vec::new()).hit(&r, 0.0, f64::INFINITY).is_none());
+        assert!(build(spheres(1)).bounding_box().is_some());
+    }
+}
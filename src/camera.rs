@@ -1,7 +1,8 @@
-use crate::diffusion::random_in_unit_disk;
+use crate::diffusion::{random_in_unit_disk, random_in_unit_polygon};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::util::degrees_to_radians;
-use crate::Ray;
-use crate::{Point3, Vec3};
+use crate::vec3::{Point3, Vec3};
 
 pub struct Camera {
     origin: Point3,
@@ -11,6 +12,8 @@ pub struct Camera {
     u: Vec3,
     v: Vec3,
     lens_radius: f64,
+    blades: u32,
+    blade_rotation: f64,
 }
 
 impl Camera {
@@ -47,11 +50,33 @@ impl Camera {
             u,
             v,
             lens_radius,
+            blades: 0,
+            blade_rotation: 0.0,
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * random_in_unit_disk();
+    /// Gives the aperture `blades` straight edges, the first corner `rotation` degrees
+    /// counter-clockwise from the camera's right. Fewer than 3 blades keep it round.
+    pub fn with_blades(self, blades: u32, rotation: f64) -> Self {
+        Self {
+            blades,
+            blade_rotation: degrees_to_radians(rotation),
+            ..self
+        }
+    }
+
+    /// Unit vector towards the right of the image.
+    pub fn right(&self) -> Vec3 {
+        self.u
+    }
+
+    pub fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Ray {
+        let lens = if self.blades >= 3 {
+            random_in_unit_polygon(self.blades, self.blade_rotation, sampler)
+        } else {
+            random_in_unit_disk(sampler)
+        };
+        let rd = self.lens_radius * lens;
         let offset = self.u * rd.x + self.v * rd.y;
 
         Ray::new(
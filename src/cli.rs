@@ -0,0 +1,499 @@
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, ValueEnum};
+use std::path::{Path, PathBuf};
+use wave_tracer::color::Color;
+use wave_tracer::environment::Environment;
+use wave_tracer::hdr::ExrPrecision;
+use wave_tracer::sampler::SamplerKind;
+use wave_tracer::settings::{CameraSettings, RenderSettings};
+use wave_tracer::tiles::TileOrder;
+use wave_tracer::vec3::Vec3;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Quality {
+    /// Small, noisy image for checking composition
+    Preview,
+    /// Full resolution and sample count
+    Final,
+}
+
+impl Quality {
+    fn apply(self, settings: &mut RenderSettings) {
+        match self {
+            Quality::Preview => {
+                settings.image_width = 400;
+                settings.samples_per_pixel = 16;
+                settings.max_depth = 10;
+            }
+            Quality::Final => {
+                let defaults = RenderSettings::default();
+                settings.image_width = defaults.image_width;
+                settings.samples_per_pixel = defaults.samples_per_pixel;
+                settings.max_depth = defaults.max_depth;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Precision {
+    /// 16-bit half floats
+    Half,
+    /// 32-bit floats
+    Float,
+}
+
+impl From<Precision> for ExrPrecision {
+    fn from(precision: Precision) -> Self {
+        match precision {
+            Precision::Half => ExrPrecision::Half,
+            Precision::Float => ExrPrecision::Float,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Sampling {
+    /// Uniform random numbers
+    Independent,
+    /// Jittered strata shuffled per dimension
+    Stratified,
+    /// Halton sequence with scrambled digits
+    Halton,
+    /// Owen-scrambled Sobol points
+    Sobol,
+    /// Lattice points dithered with blue noise across pixels
+    BlueNoise,
+}
+
+impl From<Sampling> for SamplerKind {
+    fn from(sampling: Sampling) -> Self {
+        match sampling {
+            Sampling::Independent => SamplerKind::Independent,
+            Sampling::Stratified => SamplerKind::Stratified,
+            Sampling::Halton => SamplerKind::Halton,
+            Sampling::Sobol => SamplerKind::Sobol,
+            Sampling::BlueNoise => SamplerKind::BlueNoise,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Tiling {
+    /// Left to right, top to bottom
+    Row,
+    /// Outwards from the center of the frame
+    Spiral,
+    /// Along a Hilbert curve
+    Hilbert,
+}
+
+impl From<Tiling> for TileOrder {
+    fn from(tiling: Tiling) -> Self {
+        match tiling {
+            Tiling::Row => TileOrder::Row,
+            Tiling::Spiral => TileOrder::Spiral,
+            Tiling::Hilbert => TileOrder::Hilbert,
+        }
+    }
+}
+
+/// Ray tracer based on Ray Tracing in One Weekend.
+#[derive(Debug, Parser)]
+#[command(name = "wave-tracer", version)]
+pub struct Args {
+    /// Scene description to render instead of the random scene
+    #[arg(value_name = "SCENE")]
+    pub scene: Option<PathBuf>,
+
+    /// Output image path, or `-` for stdout
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// Channel precision of OpenEXR output
+    #[arg(long, value_enum, default_value_t = Precision::Half)]
+    pub exr_precision: Precision,
+
+    /// Quality preset overriding the scene's render settings
+    #[arg(short, long, value_enum)]
+    pub quality: Option<Quality>,
+
+    /// Image width in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub width: Option<u32>,
+
+    /// Image height in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), conflicts_with = "aspect_ratio")]
+    pub height: Option<u32>,
+
+    /// Width to height ratio, e.g. `1.5`, `3:2` or `16/9`
+    #[arg(long, value_parser = parse_aspect_ratio)]
+    pub aspect_ratio: Option<f64>,
+
+    /// Samples per pixel
+    #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub samples: Option<u64>,
+
+    /// Stop sampling a pixel once the relative standard error of its mean drops below this,
+    /// taking between `--min-samples` and `--samples` samples
+    #[arg(long, value_parser = parse_non_negative)]
+    pub noise_threshold: Option<f64>,
+
+    /// Samples per pixel before the noise threshold is checked
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub min_samples: Option<u64>,
+
+    /// Also write an image of the samples taken per pixel to this path
+    #[arg(long, value_name = "PATH")]
+    pub spp_heatmap: Option<PathBuf>,
+
+    /// Render in passes of this many samples per pixel, rewriting the preview after each pass
+    #[arg(long, value_name = "SAMPLES", value_parser = clap::value_parser!(u64).range(1..))]
+    pub progressive: Option<u64>,
+
+    /// Image rewritten after every progressive pass, defaults to the output file
+    #[arg(long, value_name = "PATH", requires = "progressive")]
+    pub preview: Option<PathBuf>,
+
+    /// Save the render state to this file after every pass and on Ctrl-C
+    #[arg(long, value_name = "PATH")]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Continue the render saved in the `--checkpoint` file
+    #[arg(long, requires = "checkpoint")]
+    pub resume: bool,
+
+    /// Hand out tiles to workers connecting to this address, such as `0.0.0.0:7878`, instead of
+    /// rendering here
+    #[arg(long, value_name = "ADDRESS")]
+    pub serve: Option<String>,
+
+    /// Seconds a worker may take to return a tile before it goes to another one, 600 by default
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        requires = "serve",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub tile_timeout: Option<u64>,
+
+    /// Render tiles for the coordinator at this address; the scene and settings come from it
+    #[arg(long, value_name = "ADDRESS", conflicts_with_all = ["scene", "serve"])]
+    pub worker: Option<String>,
+
+    /// Maximum number of bounces per ray
+    #[arg(short = 'd', long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_depth: Option<u64>,
+
+    /// Number of render threads, defaults to one per core
+    #[arg(short = 'j', long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub threads: Option<u64>,
+
+    /// Width and height of the tiles the frame is rendered in
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u32).range(1..))]
+    pub tile_size: u32,
+
+    /// Order in which tiles are rendered
+    #[arg(long, value_enum, default_value_t = Tiling::Spiral)]
+    pub tile_order: Tiling,
+
+    /// Seed for sampling, and for the random scene when no scene file is given
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Sample sequence used for pixels, the lens and bounces
+    #[arg(long, value_enum)]
+    pub sampler: Option<Sampling>,
+
+    /// Trace light at sampled wavelengths instead of RGB, for dispersion and other spectral
+    /// effects
+    #[arg(long)]
+    pub spectral: bool,
+
+    /// Trace the polarization of light, for polarizing filters and the polarization images
+    #[arg(long)]
+    pub polarized: bool,
+
+    /// Also write an image of the degree of linear polarization to this path; implies
+    /// `--polarized`
+    #[arg(long, value_name = "PATH")]
+    pub dolp: Option<PathBuf>,
+
+    /// Also write an image of the angle of linear polarization, as a hue as bright as the degree
+    /// of polarization, to this path; implies `--polarized`
+    #[arg(long, value_name = "PATH")]
+    pub aolp: Option<PathBuf>,
+
+    /// Camera position as `x,y,z`
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub look_from: Option<Vec3>,
+
+    /// Point the camera looks at as `x,y,z`
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub look_at: Option<Vec3>,
+
+    /// Camera up direction as `x,y,z`
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub vup: Option<Vec3>,
+
+    /// Vertical field of view in degrees
+    #[arg(long, value_parser = parse_fov, allow_hyphen_values = true)]
+    pub vfov: Option<f64>,
+
+    /// Lens aperture diameter, 0 for a pinhole camera
+    #[arg(long, value_parser = parse_non_negative, allow_hyphen_values = true)]
+    pub aperture: Option<f64>,
+
+    /// Number of aperture blades for polygonal bokeh, 0 for a round aperture
+    #[arg(long, value_parser = parse_blades)]
+    pub aperture_blades: Option<u32>,
+
+    /// Rotation of the aperture polygon in degrees
+    #[arg(long, value_parser = parse_f64, allow_hyphen_values = true)]
+    pub aperture_rotation: Option<f64>,
+
+    /// Distance to the plane in focus
+    #[arg(long, value_parser = parse_positive, allow_hyphen_values = true)]
+    pub focus_dist: Option<f64>,
+
+    /// Solid background color as linear `r,g,b`, e.g. `0,0,0` for scenes lit only by lights
+    #[arg(long, value_parser = parse_color)]
+    pub background: Option<Environment>,
+}
+
+impl Args {
+    /// Applies the preset and explicit options on top of `base`.
+    pub fn render_settings(&self, base: RenderSettings) -> Result<RenderSettings, clap::Error> {
+        let mut settings = base;
+        let aspect_ratio = self.aspect_ratio.unwrap_or_else(|| base.aspect_ratio());
+
+        if let Some(quality) = self.quality {
+            quality.apply(&mut settings);
+        }
+        if let Some(width) = self.width {
+            settings.image_width = width;
+        }
+        settings.image_height = match self.height {
+            Some(height) => height,
+            None => (settings.image_width as f64 / aspect_ratio) as u32,
+        };
+        if settings.image_height == 0 {
+            return Err(invalid(
+                ErrorKind::ValueValidation,
+                format!(
+                    "image height for width {} and aspect ratio {} is zero",
+                    settings.image_width, aspect_ratio
+                ),
+            ));
+        }
+        if let Some(samples) = self.samples {
+            settings.samples_per_pixel = samples as usize;
+        }
+        if let Some(noise_threshold) = self.noise_threshold {
+            settings.noise_threshold = noise_threshold;
+        }
+        if let Some(min_samples) = self.min_samples {
+            settings.min_samples_per_pixel = min_samples as usize;
+        }
+        if let Some(max_depth) = self.max_depth {
+            settings.max_depth = max_depth as usize;
+        }
+        if let Some(seed) = self.seed {
+            settings.seed = seed;
+        }
+        if let Some(sampler) = self.sampler {
+            settings.sampler = sampler.into();
+        }
+        if self.spectral {
+            settings.spectral = true;
+        }
+        if self.polarized || self.dolp.is_some() || self.aolp.is_some() {
+            settings.polarized = true;
+        }
+        Ok(settings)
+    }
+
+    /// Where progressive passes write their snapshots: `--preview`, or else the output file.
+    pub fn preview_path(&self) -> Result<Option<&Path>, clap::Error> {
+        if self.progressive.is_none() {
+            return Ok(None);
+        }
+        match self.preview.as_deref().or(self.output.as_deref()) {
+            Some(path) if path != Path::new("-") => Ok(Some(path)),
+            _ => Err(invalid(
+                ErrorKind::MissingRequiredArgument,
+                "--progressive needs an --output or --preview file to write passes to".to_string(),
+            )),
+        }
+    }
+
+    /// Applies the explicit camera options on top of `base`.
+    pub fn camera_settings(&self, base: CameraSettings) -> Result<CameraSettings, clap::Error> {
+        let mut camera = base;
+        if let Some(look_from) = self.look_from {
+            camera.look_from = look_from;
+        }
+        if let Some(look_at) = self.look_at {
+            camera.look_at = look_at;
+        }
+        if let Some(vup) = self.vup {
+            camera.vup = vup;
+        }
+        if let Some(vfov) = self.vfov {
+            camera.vfov = vfov;
+        }
+        if let Some(aperture) = self.aperture {
+            camera.aperture = aperture;
+        }
+        if let Some(blades) = self.aperture_blades {
+            camera.aperture_blades = blades;
+        }
+        if let Some(rotation) = self.aperture_rotation {
+            camera.aperture_rotation = rotation;
+        }
+        if let Some(focus_dist) = self.focus_dist {
+            camera.focus_dist = focus_dist;
+        }
+
+        let view = camera.look_from - camera.look_at;
+        if view.near_zero() {
+            return Err(invalid(
+                ErrorKind::ValueValidation,
+                "--look-from and --look-at must differ".to_string(),
+            ));
+        }
+        if camera.vup.cross(view).near_zero() {
+            return Err(invalid(
+                ErrorKind::ValueValidation,
+                "--vup must not be parallel to the viewing direction".to_string(),
+            ));
+        }
+        Ok(camera)
+    }
+}
+
+fn invalid(kind: ErrorKind, message: String) -> clap::Error {
+    Args::command().error(kind, message)
+}
+
+fn parse_f64(s: &str) -> Result<f64, String> {
+    let value: f64 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("`{}` is not a number", s))?;
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(format!("`{}` is not a finite number", s))
+    }
+}
+
+fn parse_positive(s: &str) -> Result<f64, String> {
+    let value = parse_f64(s)?;
+    if value > 0.0 {
+        Ok(value)
+    } else {
+        Err("must be greater than 0".to_string())
+    }
+}
+
+fn parse_non_negative(s: &str) -> Result<f64, String> {
+    let value = parse_f64(s)?;
+    if value >= 0.0 {
+        Ok(value)
+    } else {
+        Err("must not be negative".to_string())
+    }
+}
+
+fn parse_fov(s: &str) -> Result<f64, String> {
+    let value = parse_f64(s)?;
+    if value > 0.0 && value < 180.0 {
+        Ok(value)
+    } else {
+        Err("must be between 0 and 180 degrees".to_string())
+    }
+}
+
+fn parse_blades(s: &str) -> Result<u32, String> {
+    match s.trim().parse() {
+        Ok(blades) if blades == 0 || blades >= 3 => Ok(blades),
+        _ => Err("must be 0 for a round aperture or at least 3".to_string()),
+    }
+}
+
+fn parse_aspect_ratio(s: &str) -> Result<f64, String> {
+    match s.find([':', '/']) {
+        Some(i) => Ok(parse_positive(&s[..i])? / parse_positive(&s[i + 1..])?),
+        None => parse_positive(s),
+    }
+}
+
+fn parse_vec3(s: &str) -> Result<Vec3, String> {
+    let components = s
+        .split(',')
+        .map(parse_f64)
+        .collect::<Result<Vec<f64>, String>>()?;
+    match components[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!("expected three components `x,y,z`, got `{}`", s)),
+    }
+}
+
+fn parse_color(s: &str) -> Result<Environment, String> {
+    let Vec3 { x, y, z } = parse_vec3(s)?;
+    if x < 0.0 || y < 0.0 || z < 0.0 {
+        return Err(format!(
+            "color components must not be negative, got `{}`",
+            s
+        ));
+    }
+    Ok(Environment::Solid(Color::new(x, y, z)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aspect_ratio_formats() {
+        assert_eq!(parse_aspect_ratio("1.5"), Ok(1.5));
+        assert_eq!(parse_aspect_ratio("3:2"), Ok(1.5));
+        assert_eq!(parse_aspect_ratio("16/9"), Ok(16.0 / 9.0));
+        assert!(parse_aspect_ratio("0:2").is_err());
+        assert!(parse_aspect_ratio("wide").is_err());
+    }
+
+    #[test]
+    fn vec3_format() {
+        assert_eq!(parse_vec3("13,2,-3"), Ok(Vec3::new(13.0, 2.0, -3.0)));
+        assert!(parse_vec3("1,2").is_err());
+        assert!(parse_vec3("1,2,nan").is_err());
+    }
+
+    #[test]
+    fn overrides_apply_on_top_of_preset() {
+        let args = Args::parse_from(["wave-tracer", "-q", "preview", "--width", "300", "-s", "4"]);
+        let settings = args.render_settings(RenderSettings::default()).unwrap();
+        assert_eq!(settings.image_width, 300);
+        assert_eq!(settings.image_height, 200);
+        assert_eq!(settings.samples_per_pixel, 4);
+        assert_eq!(settings.max_depth, 10);
+    }
+
+    #[test]
+    fn rejects_degenerate_camera() {
+        let args = Args::parse_from(["wave-tracer", "--look-from", "1,1,1", "--look-at", "1,1,1"]);
+        let error = args.camera_settings(CameraSettings::default()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn progressive_renders_need_a_file() {
+        let args = Args::parse_from(["wave-tracer", "--progressive", "4"]);
+        let error = args.preview_path().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::MissingRequiredArgument);
+        let args = Args::parse_from(["wave-tracer", "--progressive", "4", "-o", "out.png"]);
+        assert_eq!(args.preview_path().unwrap(), Some(Path::new("out.png")));
+    }
+}
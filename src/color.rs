@@ -1,5 +1,5 @@
 use crate::vec3::Vec3;
-use std::ops::{Add, AddAssign, Mul};
+use std::ops::{Add, AddAssign, Mul, Sub};
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Color {
@@ -8,20 +8,19 @@ pub struct Color {
     pub b: f64,
 }
 
-fn translate_color_value(n: f64) -> u32 {
-    (256.0 * n.clamp(0.0, 0.999)) as u32
+fn translate_color_value(n: f64) -> u8 {
+    (256.0 * n.clamp(0.0, 0.999)) as u8
 }
 
-pub fn get_pixel(color: Color, samples_per_pixel: usize) -> String {
-    // Divbide the color by the number of samples.
-    let scale = 1.0 / samples_per_pixel as f64;
+// Gamma correct for gamma=2.0 and quantize to 8 bits.
+pub fn get_pixel(color: Color) -> [u8; 3] {
+    let Color { r, g, b } = color;
 
-    let Color { r, g, b } = color * scale;
-
-    let ir = translate_color_value(r.sqrt());
-    let ig = translate_color_value(g.sqrt());
-    let ib = translate_color_value(b.sqrt());
-    format!("{} {} {}\n", ir, ig, ib)
+    [
+        translate_color_value(r.sqrt()),
+        translate_color_value(g.sqrt()),
+        translate_color_value(b.sqrt()),
+    ]
 }
 
 impl Add for Color {
@@ -58,6 +57,18 @@ impl AddAssign for Color {
     }
 }
 
+impl Sub for Color {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            r: self.r - other.r,
+            g: self.g - other.g,
+            b: self.b - other.b,
+        }
+    }
+}
+
 impl Mul for Color {
     type Output = Self;
 
@@ -1,33 +1,27 @@
-use crate::Vec3;
-use rand::distributions::{Distribution, Uniform};
-use rand::{thread_rng, Rng};
-
-// Simple diffuse
-pub fn random_in_unit_sphere() -> Vec3 {
-    let mut rng = thread_rng();
-    let between = Uniform::from(-1.0..1.0);
-    loop {
-        let p = Vec3::new(
-            between.sample(&mut rng),
-            between.sample(&mut rng),
-            between.sample(&mut rng),
-        );
-
-        if p.length_squared() < 1.0 {
-            return p;
-        }
-    }
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+
+// Simple diffuse, mapped directly rather than by rejection so that every call uses the same
+// number of sampler dimensions
+pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Vec3 {
+    let radius = sampler.get_1d().cbrt();
+    radius * random_unit_vector(sampler)
 }
 
 // True Lambertian
-pub fn random_unit_vector() -> Vec3 {
-    random_in_unit_sphere().unit_vector()
+pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vec3 {
+    let (r1, r2) = sampler.get_2d();
+    let z = 1.0 - 2.0 * r2;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * r1;
+    Vec3::new(phi.cos() * r, phi.sin() * r, z)
 }
 
 // Uniform scatter direction away from the hit point
 #[allow(dead_code)]
-pub fn random_in_hemisphere(normal: &Vec3) -> Vec3 {
-    let in_unit_sphere = random_in_unit_sphere();
+pub fn random_in_hemisphere(normal: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+    let in_unit_sphere = random_in_unit_sphere(sampler);
     if in_unit_sphere.dot(normal) > 0.0 {
         // In the same hemisphere as the normal
         in_unit_sphere
@@ -36,13 +30,147 @@ pub fn random_in_hemisphere(normal: &Vec3) -> Vec3 {
     }
 }
 
-pub fn random_in_unit_disk() -> Vec3 {
-    let mut rng = thread_rng();
-    loop {
-        let (x, y) = rng.gen();
-        let p = Vec3::new(x, y, 0.0);
-        if p.length_squared() < 1.0 {
-            return p;
+// Cosine-weighted direction around +z
+pub fn random_cosine_direction(sampler: &mut dyn Sampler) -> Vec3 {
+    let (r1, r2) = sampler.get_2d();
+    let phi = 2.0 * PI * r1;
+    let r = r2.sqrt();
+    Vec3::new(phi.cos() * r, phi.sin() * r, (1.0 - r2).sqrt())
+}
+
+// Uniform over the unit disk in the xy plane. The concentric mapping of Shirley and Chiu 1997
+// keeps neighbouring samples together, which preserves the stratification of the sampler.
+pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Vec3 {
+    let (u1, u2) = sampler.get_2d();
+    let (a, b) = (2.0 * u1 - 1.0, 2.0 * u2 - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+// Uniform over the regular polygon in the xy plane with `blades` corners on the unit circle,
+// the first one at `rotation` radians from the x axis.
+pub fn random_in_unit_polygon(blades: u32, rotation: f64, sampler: &mut dyn Sampler) -> Vec3 {
+    let (u1, u2) = sampler.get_2d();
+    // Pick the triangle between the center and one edge, then reuse what is left of u1.
+    let scaled = u1 * blades as f64;
+    let blade = (scaled as u32).min(blades - 1);
+    let u1 = scaled - blade as f64;
+    let corner = |k: u32| {
+        let angle = rotation + 2.0 * PI * k as f64 / blades as f64;
+        Vec3::new(angle.cos(), angle.sin(), 0.0)
+    };
+    let s = u1.sqrt();
+    s * ((1.0 - u2) * corner(blade) + u2 * corner(blade + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerKind;
+
+    const N: u64 = 20_000;
+
+    fn points(kind: SamplerKind, f: impl Fn(&mut dyn Sampler) -> Vec3) -> Vec<Vec3> {
+        (0..N)
+            .map(|i| f(&mut *kind.create(7, (0, 0), i, N as usize)))
+            .collect()
+    }
+
+    fn mean(points: &[Vec3]) -> Vec3 {
+        points
+            .iter()
+            .fold(Vec3::new(0.0, 0.0, 0.0), |acc, &p| acc + p)
+            / points.len() as f64
+    }
+
+    fn fraction(points: &[Vec3], inside: impl Fn(&Vec3) -> bool) -> f64 {
+        points.iter().filter(|p| inside(p)).count() as f64 / points.len() as f64
+    }
+
+    // Binomial standard deviation of a fraction near 1/2 is about 0.0035 at N samples, so
+    // these tolerances are roughly five sigma for the independent sampler.
+    const TOLERANCE: f64 = 0.02;
+
+    #[test]
+    fn disk_is_centered_and_uniform() {
+        for kind in [SamplerKind::Independent, SamplerKind::Sobol] {
+            let points = points(kind, random_in_unit_disk);
+            assert!(points
+                .iter()
+                .all(|p| p.length_squared() <= 1.0 && p.z == 0.0));
+            let center = mean(&points);
+            assert!(center.x.abs() < TOLERANCE && center.y.abs() < TOLERANCE);
+            // Equal areas receive equal shares: quadrants, and the disk of radius 1/sqrt(2).
+            assert!((fraction(&points, |p| p.x > 0.0 && p.y > 0.0) - 0.25).abs() < TOLERANCE);
+            assert!((fraction(&points, |p| p.x < 0.0 && p.y > 0.0) - 0.25).abs() < TOLERANCE);
+            assert!((fraction(&points, |p| p.length_squared() < 0.5) - 0.5).abs() < TOLERANCE);
+            let outer_ring = fraction(&points, |p| p.length_squared() > 0.9);
+            assert!((outer_ring - 0.1).abs() < TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn polygon_is_centered_and_uniform() {
+        for blades in [3, 5, 6] {
+            let rotation = 0.3;
+            let corner = |k: u32| {
+                let angle = rotation + 2.0 * PI * k as f64 / blades as f64;
+                Vec3::new(angle.cos(), angle.sin(), 0.0)
+            };
+            // Inside when left of every counter-clockwise edge of the polygon scaled by `scale`.
+            let inside = |p: &Vec3, scale: f64| {
+                (0..blades).all(|k| {
+                    let (a, b) = (scale * corner(k), scale * corner(k + 1));
+                    (b - a).cross(*p - a).z >= -1e-9
+                })
+            };
+            for kind in [SamplerKind::Independent, SamplerKind::Sobol] {
+                let points = points(kind, |sampler| {
+                    random_in_unit_polygon(blades, rotation, sampler)
+                });
+                assert!(points.iter().all(|p| inside(p, 1.0)));
+                let center = mean(&points);
+                assert!(center.x.abs() < TOLERANCE && center.y.abs() < TOLERANCE);
+                let half_area = fraction(&points, |p| inside(p, 0.5f64.sqrt()));
+                assert!((half_area - 0.5).abs() < TOLERANCE);
+                let upper = fraction(&points, |p| p.y > 0.0);
+                let expected = upper_area(blades, rotation);
+                assert!(
+                    (upper - expected).abs() < TOLERANCE,
+                    "{} vs {}",
+                    upper,
+                    expected
+                );
+            }
         }
     }
+
+    // Fraction of the polygon's area above the x axis, integrating r^2 / 2 of its boundary in
+    // polar form.
+    fn upper_area(blades: u32, rotation: f64) -> f64 {
+        let steps = 2000;
+        let half = PI / blades as f64;
+        let radius = |phi: f64| {
+            let local = (phi - rotation).rem_euclid(2.0 * half);
+            half.cos() / (local - half).cos()
+        };
+        let integrate = |from: f64, to: f64| {
+            (0..steps)
+                .map(|i| {
+                    let phi = from + (to - from) * (i as f64 + 0.5) / steps as f64;
+                    radius(phi).powi(2)
+                })
+                .sum::<f64>()
+                * (to - from)
+                / steps as f64
+        };
+        integrate(0.0, PI) / integrate(0.0, 2.0 * PI)
+    }
 }
@@ -0,0 +1,581 @@
+//! Rendering on several machines. A coordinator hands out the tiles of each pass to workers
+//! connected over TCP and merges the pixel statistics they send back.
+//!
+//! The coordinator sends every worker a job with the scene file and the effective settings when
+//! it connects. Once the worker has loaded the scene and says it is ready, the coordinator sends
+//! it one tile at a time together with the tile's samples so far, and hands the tile to another
+//! worker if this one does not return it within the tile timeout. Because samples only depend
+//! on their pixel and index, the frame is the same as a local render no matter which worker took
+//! which tile. Files the scene refers to must be at the same paths on every worker.
+
+use crate::bvh;
+use crate::color::Color;
+use crate::environment::{Environment, EnvironmentMap};
+use crate::film::{self, Film, PixelStats};
+use crate::progress::Progress;
+use crate::random_scene::default_scene;
+use crate::render::Renderer;
+use crate::sampler::SamplerKind;
+use crate::scene;
+use crate::settings::{CameraSettings, RenderSettings};
+use crate::tiles::Tile;
+use crate::vec3::Vec3;
+use crate::wire;
+use rayon::prelude::*;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const JOB_MAGIC: &[u8; 8] = b"WTJOB005";
+const TILE: u8 = 1;
+const DONE: u8 = 2;
+const READY: u8 = 3;
+
+/// Time a worker has to return a tile unless [`Coordinator::with_tile_timeout`] says otherwise.
+pub const DEFAULT_TILE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Everything a worker needs to render tiles of the coordinator's frame.
+pub struct Job {
+    /// Path and source of the scene file, or `None` for the random scene of the seed.
+    pub scene: Option<(PathBuf, String)>,
+    pub settings: RenderSettings,
+    pub camera: CameraSettings,
+    pub background: Environment,
+}
+
+impl Job {
+    fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(JOB_MAGIC)?;
+        match &self.scene {
+            Some((path, source)) => {
+                wire::write_u8(out, 1)?;
+                wire::write_str(out, &path.to_string_lossy())?;
+                wire::write_str(out, source)?;
+            }
+            None => wire::write_u8(out, 0)?,
+        }
+
+        let settings = &self.settings;
+        wire::write_u32(out, settings.image_width)?;
+        wire::write_u32(out, settings.image_height)?;
+        wire::write_u64(out, settings.samples_per_pixel as u64)?;
+        wire::write_u64(out, settings.min_samples_per_pixel as u64)?;
+        wire::write_f64(out, settings.noise_threshold)?;
+        wire::write_u64(out, settings.max_depth as u64)?;
+        wire::write_u64(out, settings.seed)?;
+        wire::write_u8(out, sampler_tag(settings.sampler))?;
+        wire::write_u8(out, settings.spectral as u8)?;
+        wire::write_u8(out, settings.polarized as u8)?;
+
+        let camera = &self.camera;
+        for v in [camera.look_from, camera.look_at, camera.vup] {
+            write_vec3(out, v)?;
+        }
+        wire::write_f64(out, camera.vfov)?;
+        wire::write_f64(out, camera.aperture)?;
+        wire::write_u32(out, camera.aperture_blades)?;
+        wire::write_f64(out, camera.aperture_rotation)?;
+        wire::write_f64(out, camera.focus_dist)?;
+
+        match &self.background {
+            Environment::Solid(color) => {
+                wire::write_u8(out, 0)?;
+                write_color(out, *color)
+            }
+            Environment::Gradient { bottom, top } => {
+                wire::write_u8(out, 1)?;
+                write_color(out, *bottom)?;
+                write_color(out, *top)
+            }
+            // Workers load the map themselves, like the meshes and textures of the scene.
+            Environment::Map(map) => {
+                let path = map.path().ok_or_else(|| {
+                    wire::invalid_data("environment maps must be loaded from a file".to_string())
+                })?;
+                let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+                wire::write_u8(out, 2)?;
+                wire::write_str(out, &path.to_string_lossy())?;
+                wire::write_f64(out, map.rotation())?;
+                wire::write_f64(out, map.intensity())
+            }
+        }
+    }
+
+    fn read(input: &mut dyn Read) -> io::Result<Self> {
+        wire::expect_magic(input, JOB_MAGIC, "wave-tracer coordinator of this version")?;
+        let scene = match wire::read_u8(input)? {
+            0 => None,
+            _ => Some((wire::read_string(input)?.into(), wire::read_string(input)?)),
+        };
+
+        let settings = RenderSettings {
+            image_width: wire::read_u32(input)?,
+            image_height: wire::read_u32(input)?,
+            samples_per_pixel: wire::read_u64(input)? as usize,
+            min_samples_per_pixel: wire::read_u64(input)? as usize,
+            noise_threshold: wire::read_f64(input)?,
+            max_depth: wire::read_u64(input)? as usize,
+            seed: wire::read_u64(input)?,
+            sampler: sampler_from_tag(wire::read_u8(input)?)?,
+            spectral: wire::read_u8(input)? != 0,
+            polarized: wire::read_u8(input)? != 0,
+        };
+
+        let camera = CameraSettings {
+            look_from: read_vec3(input)?,
+            look_at: read_vec3(input)?,
+            vup: read_vec3(input)?,
+            vfov: wire::read_f64(input)?,
+            aperture: wire::read_f64(input)?,
+            aperture_blades: wire::read_u32(input)?,
+            aperture_rotation: wire::read_f64(input)?,
+            focus_dist: wire::read_f64(input)?,
+        };
+
+        let background = match wire::read_u8(input)? {
+            0 => Environment::Solid(read_color(input)?),
+            1 => Environment::Gradient {
+                bottom: read_color(input)?,
+                top: read_color(input)?,
+            },
+            _ => {
+                let path = PathBuf::from(wire::read_string(input)?);
+                let rotation = wire::read_f64(input)?;
+                let intensity = wire::read_f64(input)?;
+                let map = EnvironmentMap::load(&path, rotation, intensity)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+                Environment::Map(Arc::new(map))
+            }
+        };
+
+        Ok(Self {
+            scene,
+            settings,
+            camera,
+            background,
+        })
+    }
+}
+
+fn sampler_tag(sampler: SamplerKind) -> u8 {
+    match sampler {
+        SamplerKind::Independent => 0,
+        SamplerKind::Stratified => 1,
+        SamplerKind::Halton => 2,
+        SamplerKind::Sobol => 3,
+        SamplerKind::BlueNoise => 4,
+    }
+}
+
+fn sampler_from_tag(tag: u8) -> io::Result<SamplerKind> {
+    match tag {
+        0 => Ok(SamplerKind::Independent),
+        1 => Ok(SamplerKind::Stratified),
+        2 => Ok(SamplerKind::Halton),
+        3 => Ok(SamplerKind::Sobol),
+        4 => Ok(SamplerKind::BlueNoise),
+        _ => Err(wire::invalid_data(format!("unknown sampler {}", tag))),
+    }
+}
+
+fn write_vec3(out: &mut dyn Write, v: Vec3) -> io::Result<()> {
+    for component in [v.x, v.y, v.z] {
+        wire::write_f64(out, component)?;
+    }
+    Ok(())
+}
+
+fn read_vec3(input: &mut dyn Read) -> io::Result<Vec3> {
+    Ok(Vec3::new(
+        wire::read_f64(input)?,
+        wire::read_f64(input)?,
+        wire::read_f64(input)?,
+    ))
+}
+
+fn write_color(out: &mut dyn Write, color: Color) -> io::Result<()> {
+    write_vec3(out, Vec3::new(color.r, color.g, color.b))
+}
+
+fn read_color(input: &mut dyn Read) -> io::Result<Color> {
+    let Vec3 { x, y, z } = read_vec3(input)?;
+    Ok(Color::new(x, y, z))
+}
+
+/// A tile to sample up to `samples` samples per pixel, starting from `pixels`.
+struct Work {
+    tile: Tile,
+    samples: usize,
+    pixels: Vec<PixelStats>,
+}
+
+/// Tiles waiting for a worker and tiles that came back, shared by the connection threads.
+struct Queue {
+    pending: VecDeque<Work>,
+    finished: Vec<(Work, u64)>,
+    closed: bool,
+    tile_timeout: Duration,
+    // Whether workers coming and going and finished tiles are reported on stderr.
+    report_progress: bool,
+}
+
+type Shared = Arc<(Mutex<Queue>, Condvar)>;
+
+/// Coordinator end of a distributed render.
+pub struct Coordinator {
+    shared: Shared,
+    interrupted: Option<&'static AtomicBool>,
+}
+
+impl Coordinator {
+    /// Accepts workers on `listener` in the background for as long as the coordinator lives and
+    /// sends each of them `job`.
+    pub fn new(listener: TcpListener, job: &Job) -> io::Result<Self> {
+        let mut encoded = Vec::new();
+        job.write(&mut encoded)?;
+        let job = Arc::new(encoded);
+        let queue = Queue {
+            pending: VecDeque::new(),
+            finished: Vec::new(),
+            closed: false,
+            tile_timeout: DEFAULT_TILE_TIMEOUT,
+            report_progress: false,
+        };
+        let shared: Shared = Arc::new((Mutex::new(queue), Condvar::new()));
+
+        let accepting = Arc::clone(&shared);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let shared = Arc::clone(&accepting);
+                let job = Arc::clone(&job);
+                thread::spawn(move || {
+                    let peer = stream
+                        .peer_addr()
+                        .map_or_else(|_| "?".to_string(), |address| address.to_string());
+                    let report = shared.0.lock().unwrap().report_progress;
+                    if report {
+                        eprintln!("\rWorker {} connected", peer);
+                    }
+                    if let Err(e) = serve(stream, &job, &shared) {
+                        if report {
+                            eprintln!("\rWorker {} disconnected: {}", peer, e);
+                        }
+                    }
+                });
+            }
+        });
+        Ok(Self {
+            shared,
+            interrupted: None,
+        })
+    }
+
+    /// Stops waiting for tiles as soon as `flag` is set, see [`Coordinator::render_pass`].
+    pub fn interrupted_by(mut self, flag: &'static AtomicBool) -> Self {
+        self.interrupted = Some(flag);
+        self
+    }
+
+    /// Reports workers connecting and disconnecting, and the progress of every pass, on stderr.
+    pub fn with_progress(self) -> Self {
+        self.shared.0.lock().unwrap().report_progress = true;
+        self
+    }
+
+    /// Gives up on workers that take longer than `timeout` to return a tile, which then goes to
+    /// another worker. Stalled workers and dead connections would otherwise hold their tile
+    /// forever, so this should be well above the time the slowest worker takes for a tile.
+    pub fn with_tile_timeout(self, timeout: Duration) -> Self {
+        self.shared.0.lock().unwrap().tile_timeout = timeout;
+        self
+    }
+
+    /// Renders a pass like [`Renderer::render_pass`] on the connected workers, waiting for
+    /// workers if there are none. Tiles lost with a worker or timed out go back to the others.
+    /// Returns early, dropping the tiles still out, once the render is interrupted.
+    pub fn render_pass(&self, film: &mut Film, tiles: &[Tile], samples: usize) {
+        let (queue, changed) = &*self.shared;
+        let mut queue = queue.lock().unwrap();
+        let progress = queue.report_progress.then(|| Progress::new(tiles.len()));
+        queue.pending.extend(tiles.iter().map(|&tile| Work {
+            tile,
+            samples,
+            pixels: film.read_tile(tile),
+        }));
+        changed.notify_all();
+
+        let mut remaining = tiles.len();
+        while remaining > 0 {
+            for (work, samples_taken) in queue.finished.drain(..) {
+                film.write_tile(work.tile, &work.pixels);
+                if let Some(progress) = &progress {
+                    progress.tile_done(samples_taken);
+                }
+                remaining -= 1;
+            }
+            if remaining == 0 {
+                break;
+            }
+            if self
+                .interrupted
+                .is_some_and(|flag| flag.load(Ordering::Relaxed))
+            {
+                queue.pending.clear();
+                return;
+            }
+            queue = changed
+                .wait_timeout(queue, Duration::from_millis(100))
+                .unwrap()
+                .0;
+        }
+    }
+}
+
+impl Drop for Coordinator {
+    // Lets the workers go.
+    fn drop(&mut self) {
+        let (queue, changed) = &*self.shared;
+        queue.lock().unwrap().closed = true;
+        changed.notify_all();
+    }
+}
+
+// Feeds tiles to one worker until the coordinator closes, the connection fails or the worker
+// takes too long to return a tile.
+fn serve(stream: TcpStream, job: &[u8], shared: &Shared) -> io::Result<()> {
+    let (queue, changed) = &**shared;
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    writer.write_all(job)?;
+    writer.flush()?;
+    // Loading the scene may take any time, as the worker holds no tile yet.
+    let tag = wire::read_u8(&mut reader)?;
+    if tag != READY {
+        return Err(wire::invalid_data(format!("unknown message {}", tag)));
+    }
+
+    loop {
+        let (mut work, timeout) = {
+            let mut queue = queue.lock().unwrap();
+            loop {
+                if let Some(work) = queue.pending.pop_front() {
+                    break (work, queue.tile_timeout);
+                }
+                if queue.closed {
+                    wire::write_u8(&mut writer, DONE)?;
+                    return writer.flush();
+                }
+                queue = changed.wait(queue).unwrap();
+            }
+        };
+        reader.get_ref().set_read_timeout(Some(timeout))?;
+        match exchange(&mut reader, &mut writer, &mut work) {
+            Ok(samples_taken) => queue.lock().unwrap().finished.push((work, samples_taken)),
+            Err(e) => {
+                queue.lock().unwrap().pending.push_front(work);
+                changed.notify_all();
+                return Err(e);
+            }
+        }
+        changed.notify_all();
+    }
+}
+
+// Sends `work` to a worker and stores the pixels it returns, leaving `work` untouched on errors
+// so that it can be handed to another worker.
+fn exchange(reader: &mut dyn Read, writer: &mut dyn Write, work: &mut Work) -> io::Result<u64> {
+    let Tile {
+        x,
+        y,
+        width,
+        height,
+    } = work.tile;
+    wire::write_u8(writer, TILE)?;
+    for value in [x, y, width, height] {
+        wire::write_u32(writer, value)?;
+    }
+    wire::write_u64(writer, work.samples as u64)?;
+    film::write_pixels(&work.pixels, writer)?;
+    writer.flush()?;
+
+    let mut pixels = work.pixels.clone();
+    film::read_pixels(&mut pixels, reader)?;
+    let samples_taken = wire::read_u64(reader)?;
+    work.pixels = pixels;
+    Ok(samples_taken)
+}
+
+/// Renders tiles for the coordinator at `address` until it finishes the frame.
+pub fn work(address: impl ToSocketAddrs) -> io::Result<()> {
+    let stream = TcpStream::connect(address)?;
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    let job = Job::read(&mut reader)?;
+    let scene = match &job.scene {
+        Some((path, source)) => scene::parse(source, path)
+            .map_err(|e| wire::invalid_data(format!("cannot load scene {}", e)))?,
+        None => default_scene(job.settings.seed),
+    };
+    let world = bvh::build(scene.world.objects);
+    let camera = job.camera.build(job.settings.aspect_ratio());
+    let renderer = Renderer::new(
+        &*world,
+        &scene.lights,
+        &job.background,
+        &camera,
+        job.settings,
+    );
+    wire::write_u8(&mut writer, READY)?;
+    writer.flush()?;
+
+    loop {
+        let tag = match wire::read_u8(&mut reader) {
+            Ok(tag) => tag,
+            // The coordinator exited without saying goodbye once the frame was done.
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        match tag {
+            TILE => {
+                let tile = Tile {
+                    x: wire::read_u32(&mut reader)?,
+                    y: wire::read_u32(&mut reader)?,
+                    width: wire::read_u32(&mut reader)?,
+                    height: wire::read_u32(&mut reader)?,
+                };
+                let samples = wire::read_u64(&mut reader)? as usize;
+                let mut pixels =
+                    vec![PixelStats::new(); tile.width as usize * tile.height as usize];
+                film::read_pixels(&mut pixels, &mut reader)?;
+
+                // One tile at a time, so its rows are spread over the worker's threads.
+                let samples_taken: u64 = pixels
+                    .par_chunks_mut(tile.width as usize)
+                    .enumerate()
+                    .map(|(row, line)| {
+                        let row = Tile {
+                            y: tile.y + row as u32,
+                            height: 1,
+                            ..tile
+                        };
+                        renderer.render_tile(row, line, samples)
+                    })
+                    .sum();
+
+                film::write_pixels(&pixels, &mut writer)?;
+                wire::write_u64(&mut writer, samples_taken)?;
+                writer.flush()?;
+            }
+            DONE => return Ok(()),
+            _ => return Err(wire::invalid_data(format!("unknown message {}", tag))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tiles::{self, TileOrder};
+
+    #[test]
+    fn jobs_round_trip() {
+        let job = Job {
+            scene: Some((
+                "scenes/cube.toml".into(),
+                "[render]\nwidth = 3\n".to_string(),
+            )),
+            settings: RenderSettings {
+                noise_threshold: 0.05,
+                sampler: SamplerKind::BlueNoise,
+                ..RenderSettings::default()
+            },
+            camera: CameraSettings {
+                aperture_blades: 6,
+                ..CameraSettings::default()
+            },
+            background: Environment::Solid(Color::new(0.1, 0.2, 0.3)),
+        };
+        let mut bytes = Vec::new();
+        job.write(&mut bytes).unwrap();
+        let read = Job::read(&mut &bytes[..]).unwrap();
+        assert_eq!(read.scene, job.scene);
+        assert_eq!(read.settings, job.settings);
+        assert_eq!(read.camera, job.camera);
+        assert_eq!(read.background, job.background);
+    }
+
+    #[test]
+    fn workers_render_the_same_frame_as_one_machine() {
+        let settings = RenderSettings {
+            image_width: 24,
+            image_height: 16,
+            samples_per_pixel: 4,
+            max_depth: 8,
+            seed: 5,
+            ..RenderSettings::default()
+        };
+        let job = Job {
+            scene: None,
+            settings,
+            camera: CameraSettings::default(),
+            background: Environment::default(),
+        };
+        let tiles = tiles::tiles(24, 16, 8, TileOrder::Row);
+
+        let scene = default_scene(5);
+        let camera = job.camera.build(settings.aspect_ratio());
+        let renderer = Renderer::new(
+            &scene.world,
+            &scene.lights,
+            &job.background,
+            &camera,
+            settings,
+        );
+        let mut local = Film::new(24, 16);
+        renderer.render_pass(&mut local, &tiles, 4);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let coordinator = Coordinator::new(listener, &job)
+            .unwrap()
+            .with_tile_timeout(Duration::from_secs(2));
+        let mut film = Film::new(24, 16);
+
+        // A worker that takes a tile and hangs up, and one that takes a tile and never returns
+        // it, both of which put their tile back in the queue for the workers that join later.
+        let connect = || {
+            let stream = TcpStream::connect(address).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            Job::read(&mut reader).unwrap();
+            wire::write_u8(&mut &stream, READY).unwrap();
+            reader
+        };
+        let mut flaky = connect();
+        let mut stalled = connect();
+        let workers = thread::scope(|scope| {
+            scope.spawn(|| coordinator.render_pass(&mut film, &tiles, 4));
+            assert_eq!(wire::read_u8(&mut flaky).unwrap(), TILE);
+            assert_eq!(wire::read_u8(&mut stalled).unwrap(), TILE);
+            drop(flaky);
+            (0..2)
+                .map(|_| thread::spawn(move || work(address)))
+                .collect::<Vec<_>>()
+        });
+
+        assert_eq!(film.pixels, local.pixels);
+        drop(stalled);
+        drop(coordinator);
+        for worker in workers {
+            worker.join().unwrap().unwrap();
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use crate::color::Color;
+use crate::film::luminance;
+use crate::image::{self, Framebuffer};
+use crate::pdf::Pdf;
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Radiance arriving along rays that leave the scene.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Environment {
+    Solid(Color),
+    /// Blend from `bottom` straight down to `top` straight up.
+    Gradient {
+        bottom: Color,
+        top: Color,
+    },
+    /// Equirectangular image, sampled directly like a light.
+    Map(Arc<EnvironmentMap>),
+}
+
+impl Environment {
+    pub fn color(&self, direction: Vec3) -> Color {
+        match self {
+            Environment::Solid(color) => *color,
+            Environment::Gradient { bottom, top } => {
+                let unit_direction = direction.unit_vector();
+                let t = 0.5 * (unit_direction.y + 1.0);
+                (1.0 - t) * *bottom + t * *top
+            }
+            Environment::Map(map) => map.color(direction),
+        }
+    }
+
+    /// The image map, unless there is none or it is black and so has no light to sample.
+    pub fn sampled_map(&self) -> Option<&EnvironmentMap> {
+        match self {
+            Environment::Map(map) if map.total > 0.0 => Some(map),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Environment {
+    // The white to light blue sky of Ray Tracing in One Weekend.
+    fn default() -> Self {
+        Environment::Gradient {
+            bottom: Color::new(1.0, 1.0, 1.0),
+            top: Color::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+/// Latitude-longitude image around the scene, with straight up along the top row and straight
+/// ahead down -z in the middle. Directions are importance sampled in proportion to the luminance
+/// of the texels and the solid angle they cover.
+pub struct EnvironmentMap {
+    path: Option<PathBuf>,
+    image: Framebuffer,
+    rotation: f64,
+    intensity: f64,
+    // Running sums of the texel weights, over rows and within each row, both starting from 0.
+    marginal: Vec<f64>,
+    conditional: Vec<f64>,
+    total: f64,
+}
+
+impl EnvironmentMap {
+    /// `rotation` turns the map about the vertical axis, in degrees, and `intensity` scales its
+    /// radiance.
+    pub fn new(image: Framebuffer, rotation: f64, intensity: f64) -> Self {
+        let (width, height) = (image.width as usize, image.height as usize);
+        let mut conditional = Vec::with_capacity(height * (width + 1));
+        let mut marginal = Vec::with_capacity(height + 1);
+        marginal.push(0.0);
+        for (y, row) in image.rows().enumerate() {
+            // Rows near the poles cover less of the sphere.
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+            let mut sum = 0.0;
+            conditional.push(sum);
+            for &color in row {
+                sum += luminance(color * intensity).max(0.0) * sin_theta;
+                conditional.push(sum);
+            }
+            marginal.push(marginal[y] + sum);
+        }
+        let total = marginal[height];
+        Self {
+            path: None,
+            image,
+            rotation,
+            intensity,
+            marginal,
+            conditional,
+            total,
+        }
+    }
+
+    /// Reads a map from any image [`image::read_image`] supports, normally a Radiance HDR file.
+    pub fn load(path: &Path, rotation: f64, intensity: f64) -> io::Result<Self> {
+        let image = image::read_image(path)?;
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            ..Self::new(image, rotation, intensity)
+        })
+    }
+
+    /// The file the map was loaded from.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn rotation(&self) -> f64 {
+        self.rotation
+    }
+
+    pub fn intensity(&self) -> f64 {
+        self.intensity
+    }
+
+    pub fn color(&self, direction: Vec3) -> Color {
+        let (x, y) = self.texel(self.direction_to_uv(direction));
+        self.image.pixel(x as u32, y as u32) * self.intensity
+    }
+
+    fn texel(&self, (u, v): (f64, f64)) -> (usize, usize) {
+        let (width, height) = (self.image.width as usize, self.image.height as usize);
+        (
+            ((u * width as f64) as usize).min(width - 1),
+            ((v * height as f64) as usize).min(height - 1),
+        )
+    }
+
+    // u runs once around the horizon starting behind the viewer, v from the top down.
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        let d = direction.unit_vector();
+        let theta = d.y.clamp(-1.0, 1.0).acos();
+        let phi = d.x.atan2(-d.z);
+        let u = (0.5 + phi / (2.0 * PI) - self.rotation / 360.0).rem_euclid(1.0);
+        (u, theta / PI)
+    }
+
+    fn uv_to_direction(&self, (u, v): (f64, f64)) -> Vec3 {
+        let theta = v * PI;
+        let phi = 2.0 * PI * (u - 0.5 + self.rotation / 360.0);
+        Vec3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+}
+
+impl Pdf for EnvironmentMap {
+    fn value(&self, direction: Vec3) -> f64 {
+        let d = direction.unit_vector();
+        let sin_theta = (1.0 - d.y * d.y).max(0.0).sqrt();
+        if self.total <= 0.0 || sin_theta == 0.0 {
+            return 0.0;
+        }
+        let (x, y) = self.texel(self.direction_to_uv(d));
+        let start = y * (self.image.width as usize + 1) + x;
+        let weight = self.conditional[start + 1] - self.conditional[start];
+        let texels = self.image.width as f64 * self.image.height as f64;
+        // Uniform within the texel in (u, v), which spans 2π by π radians.
+        weight / self.total * texels / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let (su, sv) = sampler.get_2d();
+        let width = self.image.width as usize;
+        let (y, fy) = sample_running_sum(&self.marginal, sv);
+        let row = &self.conditional[y * (width + 1)..(y + 1) * (width + 1)];
+        let (x, fx) = sample_running_sum(row, su);
+        self.uv_to_direction((
+            (x as f64 + fx) / width as f64,
+            (y as f64 + fy) / self.image.height as f64,
+        ))
+    }
+}
+
+impl fmt::Debug for EnvironmentMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EnvironmentMap")
+            .field("path", &self.path)
+            .field("rotation", &self.rotation)
+            .field("intensity", &self.intensity)
+            .finish()
+    }
+}
+
+// Maps loaded from the same file with the same placement are the same light. Maps made in memory
+// are only equal to themselves.
+impl PartialEq for EnvironmentMap {
+    fn eq(&self, other: &Self) -> bool {
+        (self.path.is_some() || std::ptr::eq(self, other))
+            && self.path == other.path
+            && self.rotation == other.rotation
+            && self.intensity == other.intensity
+    }
+}
+
+// Picks the interval of `sums`, running sums from 0, that `u` in [0, 1) falls in with probability
+// proportional to its width, and the position within it. Spreads evenly when all are empty.
+fn sample_running_sum(sums: &[f64], u: f64) -> (usize, f64) {
+    let n = sums.len() - 1;
+    let total = sums[n];
+    if total <= 0.0 {
+        let x = u * n as f64;
+        let i = (x as usize).min(n - 1);
+        return (i, x - i as f64);
+    }
+    let target = u * total;
+    let i = (sums.partition_point(|&sum| sum <= target).max(1) - 1).min(n - 1);
+    let width = sums[i + 1] - sums[i];
+    let f = if width > 0.0 {
+        ((target - sums[i]) / width).clamp(0.0, 1.0)
+    } else {
+        0.5
+    };
+    (i, f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diffusion::random_unit_vector;
+    use crate::sampler::IndependentSampler;
+
+    // Dim sky with a small bright sun.
+    fn sky() -> EnvironmentMap {
+        let pixels = (0..16 * 8)
+            .map(|i| match i {
+                35 => Color::new(200.0, 180.0, 150.0),
+                _ => Color::new(0.2, 0.3, 0.5 + 0.01 * (i % 16) as f64),
+            })
+            .collect();
+        EnvironmentMap::new(Framebuffer::from_pixels(16, 8, pixels), 30.0, 2.0)
+    }
+
+    #[test]
+    fn directions_round_trip_through_the_image() {
+        let map = sky();
+        for &uv in &[(0.1, 0.2), (0.5, 0.5), (0.9, 0.95), (0.001, 0.6)] {
+            let (u, v) = map.direction_to_uv(map.uv_to_direction(uv));
+            assert!(
+                (u - uv.0).abs() < 1e-9 && (v - uv.1).abs() < 1e-9,
+                "{:?}",
+                uv
+            );
+        }
+        let unrotated = EnvironmentMap::new(
+            Framebuffer::from_pixels(1, 1, vec![Color::new(1.0, 1.0, 1.0)]),
+            0.0,
+            1.0,
+        );
+        let (u, v) = unrotated.direction_to_uv(Vec3::new(0.0, 0.0, -1.0));
+        assert!((u - 0.5).abs() < 1e-12 && (v - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sampling_follows_the_luminance() {
+        let map = sky();
+        let mut sampler = IndependentSampler::new(0, (0, 0), 0);
+
+        // The density integrates to one over the sphere...
+        let n = 400_000;
+        let sum: f64 = (0..n)
+            .map(|_| map.value(random_unit_vector(&mut sampler)))
+            .sum();
+        let total = 4.0 * PI * sum / n as f64;
+        assert!((total - 1.0).abs() < 0.02, "{}", total);
+
+        // ...and estimates the radiance the map sends in, texel by texel.
+        let (width, height) = (16, 8);
+        let mut exact = 0.0;
+        for y in 0..height {
+            let theta = |y: u32| PI * y as f64 / height as f64;
+            let solid_angle = 2.0 * PI / width as f64 * (theta(y).cos() - theta(y + 1).cos());
+            for x in 0..width {
+                exact += luminance(map.image.pixel(x, y)) * 2.0 * solid_angle;
+            }
+        }
+        let n = 20_000;
+        let estimate: f64 = (0..n)
+            .map(|_| {
+                let direction = map.generate(&mut sampler);
+                luminance(map.color(direction)) / map.value(direction)
+            })
+            .sum::<f64>()
+            / n as f64;
+        assert!(
+            (estimate - exact).abs() < 0.01 * exact,
+            "{} {}",
+            estimate,
+            exact
+        );
+    }
+
+    #[test]
+    fn black_maps_are_not_sampled() {
+        let black = Framebuffer::from_pixels(2, 1, vec![Color::new(0.0, 0.0, 0.0); 2]);
+        let map = Environment::Map(Arc::new(EnvironmentMap::new(black, 0.0, 1.0)));
+        assert!(map.sampled_map().is_none());
+        assert!(Environment::default().sampled_map().is_none());
+    }
+}
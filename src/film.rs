@@ -0,0 +1,386 @@
+use crate::color::Color;
+use crate::image::Framebuffer;
+use crate::tiles::Tile;
+use crate::wire;
+use std::f64::consts::PI;
+use std::io::{self, Read, Write};
+
+/// Samples taken in one pixel. The radiance is summed, and the mean and variance of its luminance
+/// are tracked with Welford's method to tell when the pixel has converged.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PixelStats {
+    pub sum: Color,
+    pub count: u64,
+    /// Summed luminance of the second and third Stokes components against the horizontal of the
+    /// image, for polarized renders.
+    pub polarization: [f64; 2],
+    mean: f64,
+    m2: f64,
+}
+
+// Darker means are measured against this one, so that black pixels with the odd firefly do not
+// take every sample chasing a relative error.
+const MIN_RELATIVE_MEAN: f64 = 1e-3;
+
+impl PixelStats {
+    pub fn new() -> Self {
+        Self {
+            sum: Color::new(0.0, 0.0, 0.0),
+            count: 0,
+            polarization: [0.0, 0.0],
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, color: Color) {
+        self.sum += color;
+        self.count += 1;
+        let y = luminance(color);
+        let delta = y - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (y - self.mean);
+    }
+
+    /// Adds a sample of polarized light with the Stokes components `radiance`, `s1` and `s2`.
+    pub fn add_polarized(&mut self, radiance: Color, s1: Color, s2: Color) {
+        self.add(radiance);
+        self.polarization[0] += luminance(s1);
+        self.polarization[1] += luminance(s2);
+    }
+
+    /// Degree of linear polarization of the mean, from 0 to 1, and its angle in radians
+    /// counter-clockwise from the horizontal of the image, from -π/2 to π/2.
+    pub fn linear_polarization(&self) -> (f64, f64) {
+        let [s1, s2] = self.polarization;
+        let s0 = luminance(self.sum);
+        let degree = if s0 > 0.0 {
+            (s1.hypot(s2) / s0).min(1.0)
+        } else {
+            0.0
+        };
+        (degree, 0.5 * s2.atan2(s1))
+    }
+
+    pub fn mean(&self) -> Color {
+        if self.count == 0 {
+            self.sum
+        } else {
+            self.sum * (1.0 / self.count as f64)
+        }
+    }
+
+    /// Unbiased sample variance of the luminance.
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            self.m2 / (self.count - 1) as f64
+        }
+    }
+
+    /// Standard error of the mean luminance relative to the mean, unknown and so infinite until
+    /// there are two samples to estimate it from.
+    pub fn relative_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        (self.variance() / self.count as f64).sqrt() / self.mean.abs().max(MIN_RELATIVE_MEAN)
+    }
+}
+
+impl Default for PixelStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Relative luminance of linear sRGB.
+pub fn luminance(color: Color) -> f64 {
+    0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b
+}
+
+/// Per-pixel sample statistics, stored row by row from the top left corner like a
+/// [`Framebuffer`].
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<PixelStats>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![PixelStats::new(); width as usize * height as usize],
+        }
+    }
+
+    /// Copies out the pixels of `tile`, row by row.
+    pub fn read_tile(&self, tile: Tile) -> Vec<PixelStats> {
+        (tile.y..tile.y + tile.height)
+            .flat_map(|y| {
+                let start = y as usize * self.width as usize + tile.x as usize;
+                self.pixels[start..start + tile.width as usize]
+                    .iter()
+                    .copied()
+            })
+            .collect()
+    }
+
+    /// Stores the pixels of `tile` as returned by [`Film::read_tile`].
+    pub fn write_tile(&mut self, tile: Tile, pixels: &[PixelStats]) {
+        for (y, row) in (tile.y..).zip(pixels.chunks(tile.width as usize)) {
+            let start = y as usize * self.width as usize + tile.x as usize;
+            self.pixels[start..start + row.len()].copy_from_slice(row);
+        }
+    }
+
+    /// Mean radiance of every pixel.
+    pub fn to_framebuffer(&self) -> Framebuffer {
+        let pixels = self.pixels.iter().map(PixelStats::mean).collect();
+        Framebuffer::from_pixels(self.width, self.height, pixels)
+    }
+
+    /// Samples taken per pixel, from black for none through blue and red to white for
+    /// `max_samples`.
+    pub fn sample_heatmap(&self, max_samples: usize) -> Framebuffer {
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|stats| heat(stats.count as f64 / max_samples.max(1) as f64))
+            .collect();
+        Framebuffer::from_pixels(self.width, self.height, pixels)
+    }
+
+    /// Degree of linear polarization of every pixel, from black for unpolarized light to white
+    /// for fully polarized light.
+    pub fn polarization_degree(&self) -> Framebuffer {
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|stats| {
+                let (degree, _) = stats.linear_polarization();
+                Color::new(degree, degree, degree)
+            })
+            .collect();
+        Framebuffer::from_pixels(self.width, self.height, pixels)
+    }
+
+    /// Angle of linear polarization of every pixel as a hue, red for horizontal through green
+    /// at 60 degrees and blue at 120, and as bright as the light is polarized.
+    pub fn polarization_angle(&self) -> Framebuffer {
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|stats| {
+                let (degree, angle) = stats.linear_polarization();
+                hue((angle / PI).rem_euclid(1.0)) * degree
+            })
+            .collect();
+        Framebuffer::from_pixels(self.width, self.height, pixels)
+    }
+}
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"WTCHKPT2";
+
+/// Hash of `bytes` to identify inputs such as the scene file by in a checkpoint fingerprint. It
+/// stays the same across platforms and Rust releases, so checkpoints survive a rebuild.
+pub fn fingerprint_hash(bytes: &[u8]) -> u64 {
+    wire::fnv1a(bytes)
+}
+
+impl Film {
+    /// Saves the statistics of every pixel. Samples are derived from the seed, the pixel and the
+    /// sample index alone, so the sample counts are all of the random number state a resumed
+    /// render needs. `fingerprint` describes the settings the film was rendered with.
+    pub fn write_checkpoint(&self, fingerprint: &str, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(CHECKPOINT_MAGIC)?;
+        wire::write_str(out, fingerprint)?;
+        wire::write_u32(out, self.width)?;
+        wire::write_u32(out, self.height)?;
+        write_pixels(&self.pixels, out)
+    }
+
+    /// Loads a film saved by [`Film::write_checkpoint`] with the same `fingerprint`.
+    pub fn read_checkpoint(fingerprint: &str, input: &mut dyn Read) -> io::Result<Self> {
+        wire::expect_magic(input, CHECKPOINT_MAGIC, "wave-tracer checkpoint")?;
+        let saved = wire::read_string(input)?;
+        if saved != fingerprint {
+            return Err(wire::invalid_data(format!(
+                "checkpoint was saved with different settings: {}",
+                saved
+            )));
+        }
+        let width = wire::read_u32(input)?;
+        let height = wire::read_u32(input)?;
+        let mut film = Self::new(width, height);
+        read_pixels(&mut film.pixels, input)?;
+        Ok(film)
+    }
+}
+
+/// Writes the exact state of `pixels`, to be read back by [`read_pixels`].
+pub fn write_pixels(pixels: &[PixelStats], out: &mut dyn Write) -> io::Result<()> {
+    for stats in pixels {
+        let Color { r, g, b } = stats.sum;
+        let [s1, s2] = stats.polarization;
+        for value in [r, g, b, s1, s2, stats.mean, stats.m2] {
+            wire::write_f64(out, value)?;
+        }
+        wire::write_u64(out, stats.count)?;
+    }
+    Ok(())
+}
+
+/// Fills `pixels` with statistics written by [`write_pixels`].
+pub fn read_pixels(pixels: &mut [PixelStats], input: &mut dyn Read) -> io::Result<()> {
+    for stats in pixels {
+        let mut values = [0.0; 7];
+        for value in &mut values {
+            *value = wire::read_f64(input)?;
+        }
+        let [r, g, b, s1, s2, mean, m2] = values;
+        *stats = PixelStats {
+            sum: Color::new(r, g, b),
+            count: wire::read_u64(input)?,
+            polarization: [s1, s2],
+            mean,
+            m2,
+        };
+    }
+    Ok(())
+}
+
+// Fully saturated color of hue `h` in [0, 1), from red through green and blue back to red.
+fn hue(h: f64) -> Color {
+    let channel = |offset: f64| {
+        let d = (h - offset)
+            .rem_euclid(1.0)
+            .min((offset - h).rem_euclid(1.0));
+        (2.0 - 6.0 * d).clamp(0.0, 1.0)
+    };
+    Color::new(channel(0.0), channel(1.0 / 3.0), channel(2.0 / 3.0))
+}
+
+// Piecewise linear black, blue, red, yellow, white ramp over [0, 1].
+fn heat(t: f64) -> Color {
+    const STOPS: [Color; 5] = [
+        Color {
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
+        },
+        Color {
+            r: 0.0,
+            g: 0.0,
+            b: 1.0,
+        },
+        Color {
+            r: 1.0,
+            g: 0.0,
+            b: 0.0,
+        },
+        Color {
+            r: 1.0,
+            g: 1.0,
+            b: 0.0,
+        },
+        Color {
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+        },
+    ];
+    let x = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let i = (x as usize).min(STOPS.len() - 2);
+    let f = x - i as f64;
+    STOPS[i] * (1.0 - f) + STOPS[i + 1] * f
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn welford_matches_two_pass_statistics() {
+        let values = [0.5, 1.5, 0.25, 3.0, 2.0, 0.75];
+        let mut stats = PixelStats::new();
+        for &v in &values {
+            stats.add(Color::new(v, v, v));
+        }
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        assert_eq!(stats.count, 6);
+        assert!((stats.mean().g - mean).abs() < 1e-12);
+        assert!((stats.variance() - variance).abs() < 1e-12);
+        assert!((stats.relative_error() - (variance / n).sqrt() / mean).abs() < 1e-12);
+    }
+
+    #[test]
+    fn constant_pixels_converge() {
+        let mut stats = PixelStats::new();
+        assert_eq!(stats.relative_error(), f64::INFINITY);
+        stats.add(Color::new(0.2, 0.4, 0.6));
+        assert_eq!(stats.relative_error(), f64::INFINITY);
+        for _ in 0..3 {
+            stats.add(Color::new(0.2, 0.4, 0.6));
+        }
+        assert_eq!(stats.relative_error(), 0.0);
+    }
+
+    #[test]
+    fn fingerprint_hashes_are_fnv1a() {
+        assert_eq!(fingerprint_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fingerprint_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fingerprint_hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn checkpoints_round_trip() {
+        let mut film = Film::new(3, 2);
+        for (i, stats) in film.pixels.iter_mut().enumerate() {
+            for k in 0..i {
+                let color = Color::new(0.1 * k as f64, 1.0 / (k + 1) as f64, 0.3);
+                stats.add_polarized(color, color * 0.5, color * -0.25);
+            }
+        }
+        let mut bytes = Vec::new();
+        film.write_checkpoint("seed 1", &mut bytes).unwrap();
+
+        let loaded = Film::read_checkpoint("seed 1", &mut &bytes[..]).unwrap();
+        assert_eq!((loaded.width, loaded.height), (3, 2));
+        assert_eq!(loaded.pixels, film.pixels);
+        assert!(Film::read_checkpoint("seed 2", &mut &bytes[..]).is_err());
+        assert!(Film::read_checkpoint("seed 1", &mut &bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn polarization_is_measured_from_the_horizontal() {
+        let mut stats = PixelStats::new();
+        let white = Color::new(1.0, 1.0, 1.0);
+        let black = Color::new(0.0, 0.0, 0.0);
+        assert_eq!(stats.linear_polarization(), (0.0, 0.0));
+        // Half of the light polarized vertically
+        stats.add_polarized(white, white * -0.5, black);
+        let (degree, angle) = stats.linear_polarization();
+        assert!((degree - 0.5).abs() < 1e-9);
+        assert!((angle - PI / 2.0).abs() < 1e-9);
+        // Half of it at 45 degrees
+        stats.add_polarized(white, white * 0.5, white);
+        let (degree, angle) = stats.linear_polarization();
+        assert!((degree - 0.5).abs() < 1e-9);
+        assert!((angle - PI / 4.0).abs() < 1e-9);
+        assert_eq!(hue(0.0), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(hue(2.0 / 3.0), Color::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn heatmap_ramps_from_black_to_white() {
+        assert_eq!(heat(0.0), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(heat(0.25), Color::new(0.0, 0.0, 1.0));
+        assert_eq!(heat(1.0), Color::new(1.0, 1.0, 1.0));
+    }
+}
@@ -0,0 +1,486 @@
+use crate::color::Color;
+use crate::image::{next_token, Framebuffer, ImageWriter};
+use crate::wire::invalid_data;
+use std::io::{self, Write};
+
+/// Portable float map: three little-endian `f32` per pixel, rows stored bottom to top.
+pub struct PfmWriter;
+
+impl ImageWriter for PfmWriter {
+    fn write(&self, frame: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+        // A negative scale marks the data as little-endian.
+        write!(out, "PF\n{} {}\n-1.0\n", frame.width, frame.height)?;
+        for row in frame.rows().rev() {
+            let mut bytes = Vec::with_capacity(row.len() * 12);
+            for color in row {
+                for channel in [color.r, color.g, color.b] {
+                    bytes.extend_from_slice(&(channel as f32).to_le_bytes());
+                }
+            }
+            out.write_all(&bytes)?;
+        }
+        Ok(())
+    }
+}
+
+/// Radiance RGBE (`.hdr`) image with flat, uncompressed scanlines.
+pub struct RgbeWriter;
+
+impl ImageWriter for RgbeWriter {
+    fn write(&self, frame: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+        write!(
+            out,
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            frame.height, frame.width
+        )?;
+        for row in frame.rows() {
+            let bytes: Vec<u8> = row.iter().flat_map(|&color| to_rgbe(color)).collect();
+            out.write_all(&bytes)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a color (`PF`) or grayscale (`Pf`) portable float map of either byte order.
+pub fn decode_pfm(data: &[u8]) -> io::Result<Framebuffer> {
+    let malformed = || invalid_data("malformed PFM header".to_string());
+    let mut rest = data;
+    let channels = match next_token(&mut rest) {
+        Some(b"PF") => 3,
+        Some(b"Pf") => 1,
+        _ => return Err(invalid_data("not a PF or Pf float map".to_string())),
+    };
+    let mut number = || -> io::Result<f64> {
+        next_token(&mut rest)
+            .and_then(|token| std::str::from_utf8(token).ok()?.parse().ok())
+            .ok_or_else(malformed)
+    };
+    let (width, height, scale) = (number()?, number()?, number()?);
+    if width < 1.0 || height < 1.0 || width.fract() != 0.0 || height.fract() != 0.0 {
+        return Err(malformed());
+    }
+    let (width, height) = (width as u32, height as u32);
+    // A single whitespace byte separates the header from the raster.
+    let raster = rest.get(1..).unwrap_or_default();
+    let row_size = width as usize * channels * 4;
+    if raster.len() < row_size * height as usize {
+        return Err(invalid_data("PFM image is truncated".to_string()));
+    }
+    let value = |bytes: &[u8]| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        let value = if scale < 0.0 {
+            f32::from_le_bytes(bytes)
+        } else {
+            f32::from_be_bytes(bytes)
+        };
+        value as f64
+    };
+    // Rows are stored bottom to top.
+    let pixels = raster[..row_size * height as usize]
+        .chunks_exact(row_size)
+        .rev()
+        .flat_map(|row| row.chunks_exact(channels * 4))
+        .map(|pixel| match channels {
+            1 => {
+                let v = value(pixel);
+                Color::new(v, v, v)
+            }
+            _ => Color::new(
+                value(&pixel[0..4]),
+                value(&pixel[4..8]),
+                value(&pixel[8..12]),
+            ),
+        })
+        .collect();
+    Ok(Framebuffer::from_pixels(width, height, pixels))
+}
+
+/// Reads a Radiance RGBE (`.hdr`) image stored top to bottom, with flat or run-length encoded
+/// scanlines.
+pub fn decode_rgbe(data: &[u8]) -> io::Result<Framebuffer> {
+    let malformed = |what: &str| invalid_data(format!("malformed Radiance HDR {}", what));
+    let mut rest = data;
+    let mut line = || {
+        let end = rest.iter().position(|&b| b == b'\n')?;
+        let (line, tail) = rest.split_at(end);
+        rest = &tail[1..];
+        Some(String::from_utf8_lossy(line).into_owned())
+    };
+    let signature = line().ok_or_else(|| malformed("header"))?;
+    if !signature.starts_with("#?") {
+        return Err(invalid_data("not a Radiance HDR image".to_string()));
+    }
+    loop {
+        match line().ok_or_else(|| malformed("header"))?.trim() {
+            "" => break,
+            format if format.starts_with("FORMAT=") && format != "FORMAT=32-bit_rle_rgbe" => {
+                return Err(invalid_data(format!("unsupported Radiance HDR {}", format)))
+            }
+            _ => {}
+        }
+    }
+    let resolution = line().ok_or_else(|| malformed("resolution"))?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (
+            height.parse::<u32>().map_err(|_| malformed("resolution"))?,
+            width.parse::<u32>().map_err(|_| malformed("resolution"))?,
+        ),
+        _ => {
+            return Err(invalid_data(format!(
+                "unsupported Radiance HDR orientation `{}`, expected -Y height +X width",
+                resolution.trim()
+            )))
+        }
+    };
+    if width == 0 || height == 0 {
+        return Err(malformed("resolution"));
+    }
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    let mut scanline = vec![[0u8; 4]; width as usize];
+    for _ in 0..height {
+        read_scanline(&mut rest, &mut scanline)
+            .ok_or_else(|| invalid_data("Radiance HDR image is truncated".to_string()))?;
+        pixels.extend(scanline.iter().map(|&rgbe| from_rgbe(rgbe)));
+    }
+    Ok(Framebuffer::from_pixels(width, height, pixels))
+}
+
+// Fills `scanline` from the start of `data`, run-length encoded channel by channel when it
+// starts with the 2, 2 marker and flat otherwise.
+fn read_scanline(data: &mut &[u8], scanline: &mut [[u8; 4]]) -> Option<()> {
+    let width = scanline.len();
+    let encoded = (8..0x8000).contains(&width)
+        && data.len() >= 4
+        && data[0] == 2
+        && data[1] == 2
+        && ((data[2] as usize) << 8 | data[3] as usize) == width;
+    if !encoded {
+        let bytes = data.get(..4 * width)?;
+        for (pixel, rgbe) in scanline.iter_mut().zip(bytes.chunks_exact(4)) {
+            pixel.copy_from_slice(rgbe);
+        }
+        *data = &data[4 * width..];
+        return Some(());
+    }
+    *data = &data[4..];
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let (&count, tail) = data.split_first()?;
+            if count == 0 {
+                return None;
+            } else if count > 128 {
+                let run = (count - 128) as usize;
+                let (&value, tail) = tail.split_first()?;
+                for pixel in scanline.get_mut(x..x + run)? {
+                    pixel[channel] = value;
+                }
+                *data = tail;
+                x += run;
+            } else {
+                let run = count as usize;
+                let values = tail.get(..run)?;
+                for (pixel, &value) in scanline.get_mut(x..x + run)?.iter_mut().zip(values) {
+                    pixel[channel] = value;
+                }
+                *data = &tail[run..];
+                x += run;
+            }
+        }
+    }
+    Some(())
+}
+
+/// Inverse of [`to_rgbe`], up to its quantization.
+pub fn from_rgbe([r, g, b, e]: [u8; 4]) -> Color {
+    if e == 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    let scale = 2f64.powi(e as i32 - (128 + 8));
+    Color::new(r as f64 * scale, g as f64 * scale, b as f64 * scale)
+}
+
+// Shared exponent encoding, see Greg Ward's Real Pixels in Graphics Gems II.
+pub fn to_rgbe(color: Color) -> [u8; 4] {
+    let Color { r, g, b } = color;
+    let v = r.max(g).max(b);
+    if v.is_nan() || v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    let (mantissa, exponent) = frexp(v);
+    let scale = mantissa * 256.0 / v;
+    let channel = |c: f64| (c * scale).clamp(0.0, 255.0) as u8;
+    [
+        channel(r),
+        channel(g),
+        channel(b),
+        (exponent + 128).clamp(0, 255) as u8,
+    ]
+}
+
+// Splits `v > 0` into a mantissa in [0.5, 1) and a power of two.
+fn frexp(v: f64) -> (f64, i32) {
+    let mut exponent = v.log2().floor() as i32 + 1;
+    let mut mantissa = v / 2f64.powi(exponent);
+    if mantissa >= 1.0 {
+        mantissa /= 2.0;
+        exponent += 1;
+    } else if mantissa < 0.5 {
+        mantissa *= 2.0;
+        exponent -= 1;
+    }
+    (mantissa, exponent)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExrPrecision {
+    Half,
+    Float,
+}
+
+/// Uncompressed scanline OpenEXR image with R, G and B channels.
+pub struct ExrWriter {
+    pub precision: ExrPrecision,
+}
+
+impl ExrWriter {
+    fn header(&self, width: u32, height: u32) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&20000630i32.to_le_bytes());
+        header.extend_from_slice(&2i32.to_le_bytes()); // version 2, single part scanline
+
+        let pixel_type: i32 = match self.precision {
+            ExrPrecision::Half => 1,
+            ExrPrecision::Float => 2,
+        };
+        // Channels are stored in alphabetical order.
+        let mut channels = Vec::new();
+        for name in [b'B', b'G', b'R'] {
+            channels.extend_from_slice(&[name, 0]);
+            channels.extend_from_slice(&pixel_type.to_le_bytes());
+            channels.extend_from_slice(&[0, 0, 0, 0]); // pLinear and reserved
+            channels.extend_from_slice(&1i32.to_le_bytes()); // x sampling
+            channels.extend_from_slice(&1i32.to_le_bytes()); // y sampling
+        }
+        channels.push(0);
+
+        let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+
+        let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+            header.extend_from_slice(name.as_bytes());
+            header.push(0);
+            header.extend_from_slice(kind.as_bytes());
+            header.push(0);
+            header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+            header.extend_from_slice(value);
+        };
+        attribute("channels", "chlist", &channels);
+        attribute("compression", "compression", &[0]);
+        attribute("dataWindow", "box2i", &window);
+        attribute("displayWindow", "box2i", &window);
+        attribute("lineOrder", "lineOrder", &[0]);
+        attribute("pixelAspectRatio", "float", &1f32.to_le_bytes());
+        attribute("screenWindowCenter", "v2f", &[0; 8]);
+        attribute("screenWindowWidth", "float", &1f32.to_le_bytes());
+        header.push(0);
+        header
+    }
+
+    fn encode(&self, value: f64, bytes: &mut Vec<u8>) {
+        match self.precision {
+            ExrPrecision::Half => bytes.extend_from_slice(&f32_to_f16(value as f32).to_le_bytes()),
+            ExrPrecision::Float => bytes.extend_from_slice(&(value as f32).to_le_bytes()),
+        }
+    }
+}
+
+impl ImageWriter for ExrWriter {
+    fn write(&self, frame: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+        let header = self.header(frame.width, frame.height);
+        let bytes_per_value = match self.precision {
+            ExrPrecision::Half => 2,
+            ExrPrecision::Float => 4,
+        };
+        let line_size = 3 * bytes_per_value * frame.width as u64;
+
+        out.write_all(&header)?;
+        // One scanline per chunk, each prefixed by its y coordinate and data size.
+        let first_chunk = header.len() as u64 + 8 * frame.height as u64;
+        for y in 0..frame.height as u64 {
+            out.write_all(&(first_chunk + y * (8 + line_size)).to_le_bytes())?;
+        }
+        for (y, row) in frame.rows().enumerate() {
+            let mut chunk = Vec::with_capacity(8 + line_size as usize);
+            chunk.extend_from_slice(&(y as i32).to_le_bytes());
+            chunk.extend_from_slice(&(line_size as i32).to_le_bytes());
+            for channel in [|c: &Color| c.b, |c: &Color| c.g, |c: &Color| c.r] {
+                for color in row {
+                    self.encode(channel(color), &mut chunk);
+                }
+            }
+            out.write_all(&chunk)?;
+        }
+        Ok(())
+    }
+}
+
+/// Rounds to the nearest IEEE 754 half precision value, ties to even.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        // Infinity stays infinity, NaN stays a quiet NaN
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if half_exponent <= 0 {
+        // Subnormal half, or too small and flushed to zero
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - half_exponent) as u32;
+        return sign | round_shifted(mantissa, shift) as u16;
+    }
+
+    // A mantissa overflow when rounding correctly carries into the exponent.
+    sign | round_shifted(((half_exponent as u32) << 23) | mantissa, 13) as u16
+}
+
+fn round_shifted(value: u32, shift: u32) -> u32 {
+    let round_bit = 1 << (shift - 1);
+    let shifted = value >> shift;
+    // Round up above the halfway point, or at it when the result is odd
+    if value & round_bit != 0 && value & (3 * round_bit - 1) != 0 {
+        shifted + 1
+    } else {
+        shifted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    #[test]
+    fn half_conversion() {
+        assert_eq!(f32_to_f16(0.0), 0x0000);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(0.5), 0x3800);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16(1.0e6), 0x7c00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NAN) & 0x7e00, 0x7e00);
+        // Smallest subnormal and values around it
+        assert_eq!(f32_to_f16(2f32.powi(-24)), 0x0001);
+        assert_eq!(f32_to_f16(2f32.powi(-26)), 0x0000);
+        // 1 + 2^-11 is halfway between two halves and rounds to even
+        assert_eq!(f32_to_f16(1.0 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(f32_to_f16(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+    }
+
+    #[test]
+    fn rgbe_encoding() {
+        assert_eq!(to_rgbe(Color::new(0.0, 0.0, 0.0)), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe(Color::new(1.0, 0.5, 0.0)), [128, 64, 0, 129]);
+        assert_eq!(to_rgbe(Color::new(4.0, 4.0, 4.0)), [128, 128, 128, 131]);
+    }
+
+    fn frame() -> Framebuffer {
+        Framebuffer::from_pixels(
+            2,
+            2,
+            vec![
+                Color::new(1.0, 2.0, 3.0),
+                Color::new(4.0, 5.0, 6.0),
+                Color::new(7.0, 8.0, 9.0),
+                Color::new(10.0, 11.0, 12.0),
+            ],
+        )
+    }
+
+    #[test]
+    fn pfm_rows_bottom_up() {
+        let mut out = Vec::new();
+        PfmWriter.write(&frame(), &mut out).unwrap();
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&out[..header.len()], header);
+        let first = f32::from_le_bytes(out[header.len()..header.len() + 4].try_into().unwrap());
+        assert_eq!(first, 7.0);
+        assert_eq!(out.len(), header.len() + 4 * 3 * 4);
+    }
+
+    #[test]
+    fn float_maps_round_trip() {
+        let mut out = Vec::new();
+        PfmWriter.write(&frame(), &mut out).unwrap();
+        let read = decode_pfm(&out).unwrap();
+        assert_eq!((read.width, read.height), (2, 2));
+        assert_eq!(read.pixel(0, 1), Color::new(7.0, 8.0, 9.0));
+        assert_eq!(read.pixel(1, 0), Color::new(4.0, 5.0, 6.0));
+    }
+
+    #[test]
+    fn rgbe_round_trips_flat_and_run_length_encoded() {
+        let mut out = Vec::new();
+        RgbeWriter.write(&frame(), &mut out).unwrap();
+        let read = decode_rgbe(&out).unwrap();
+        assert_eq!(read.pixel(1, 1), Color::new(10.0, 11.0, 12.0));
+        assert!((read.pixel(0, 0).b - 3.0).abs() < 0.02);
+
+        // One scanline of 8 pixels: a run of 8 in red and blue, literals in green, exponent 129
+        let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+        data.extend_from_slice(&[2, 2, 0, 8]);
+        data.extend_from_slice(&[128 + 8, 128]);
+        data.extend_from_slice(&[8, 0, 16, 32, 48, 64, 80, 96, 112]);
+        data.extend_from_slice(&[128 + 8, 64]);
+        data.extend_from_slice(&[128 + 8, 129]);
+        let read = decode_rgbe(&data).unwrap();
+        assert_eq!(read.pixel(0, 0), Color::new(1.0, 0.0, 0.5));
+        assert_eq!(read.pixel(7, 0), Color::new(1.0, 0.875, 0.5));
+        assert!(decode_rgbe(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn exr_layout() {
+        for (precision, bytes) in [(ExrPrecision::Half, 2), (ExrPrecision::Float, 4)] {
+            let writer = ExrWriter { precision };
+            let mut out = Vec::new();
+            writer.write(&frame(), &mut out).unwrap();
+
+            let header_len = writer.header(2, 2).len();
+            let offset = |y: usize| {
+                let start = header_len + 8 * y;
+                u64::from_le_bytes(out[start..start + 8].try_into().unwrap()) as usize
+            };
+            let line_size = 3 * 2 * bytes;
+            assert_eq!(out.len(), header_len + 16 + 2 * (8 + line_size));
+            assert_eq!(offset(0), header_len + 16);
+            assert_eq!(offset(1), offset(0) + 8 + line_size);
+
+            // Second scanline starts with y = 1 and the blue channel
+            let chunk = &out[offset(1)..];
+            assert_eq!(i32::from_le_bytes(chunk[0..4].try_into().unwrap()), 1);
+            let blue = match precision {
+                ExrPrecision::Half => f32_to_f16(9.0).to_le_bytes().to_vec(),
+                ExrPrecision::Float => 9f32.to_le_bytes().to_vec(),
+            };
+            assert_eq!(&chunk[8..8 + bytes], &blue[..]);
+        }
+    }
+}
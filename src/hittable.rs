@@ -1,5 +1,7 @@
+use crate::aabb::Aabb;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::{Point3, Vec3};
 
 pub struct HitRecord<'a> {
@@ -7,6 +9,9 @@ pub struct HitRecord<'a> {
     pub normal: Vec3,
     pub material: &'a dyn Material,
     pub t: f64,
+    /// Surface coordinates for texture lookups.
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
 }
 
@@ -16,18 +21,21 @@ impl<'a> HitRecord<'a> {
         t: f64,
         r: &Ray,
         outward_normal: &Vec3,
+        (u, v): (f64, f64),
         material: &'a dyn Material,
     ) -> Self {
         let front_face = r.direction.dot(outward_normal) < 0.0;
         let normal = if front_face {
-            outward_normal.clone()
+            *outward_normal
         } else {
-            -outward_normal.clone()
+            -*outward_normal
         };
         Self {
             p,
             normal,
             t,
+            u,
+            v,
             front_face,
             material,
         }
@@ -35,5 +43,19 @@ impl<'a> HitRecord<'a> {
 }
 
 pub trait Hittable: Sync {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>>;
+
+    /// Box enclosing the object, or `None` if it is unbounded.
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    /// Solid angle density of `random` picking `direction` from `origin`, 0 for objects that
+    /// cannot be sampled.
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    /// Direction from `origin` towards a random point of the object.
+    fn random(&self, _origin: Point3, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
 }
@@ -1,13 +1,16 @@
+use crate::aabb::Aabb;
 use crate::hittable::HitRecord;
 use crate::hittable::Hittable;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::{Point3, Vec3};
 
 pub struct HittableList {
     pub objects: Vec<Box<dyn Hittable>>,
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let (hit_record, _) = self.objects.iter().fold((None, t_max), |acc, x| {
             let (_, closest_so_far) = acc;
             let hit_record = x.hit(r, t_min, closest_so_far);
@@ -19,6 +22,29 @@ impl Hittable for HittableList {
                 None => acc,
             }
         });
-        return hit_record;
+        hit_record
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (first, rest) = self.objects.split_first()?;
+        rest.iter().try_fold(first.bounding_box()?, |acc, object| {
+            Some(acc.surrounding_box(&object.bounding_box()?))
+        })
+    }
+
+    // Picks one object uniformly, so the density is the average over all of them.
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let sum: f64 = self
+            .objects
+            .iter()
+            .map(|object| object.pdf_value(origin, direction))
+            .sum();
+        sum / self.objects.len() as f64
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let n = self.objects.len();
+        let i = ((sampler.get_1d() * n as f64) as usize).min(n - 1);
+        self.objects[i].random(origin, sampler)
     }
 }
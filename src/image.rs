@@ -0,0 +1,332 @@
+use crate::color::{get_pixel, Color};
+use crate::hdr::{self, ExrPrecision, ExrWriter, PfmWriter, RgbeWriter};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Linear radiance per pixel, stored row by row from the top left corner.
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<Color>,
+}
+
+impl Framebuffer {
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width as usize * height as usize);
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        self.pixels[y as usize * self.width as usize + x as usize]
+    }
+
+    pub fn rows(&self) -> std::slice::Chunks<'_, Color> {
+        self.pixels.chunks(self.width as usize)
+    }
+
+    /// Gamma corrected and quantized to 8 bits per channel.
+    pub fn to_image(&self) -> ImageBuffer {
+        let pixels = self.pixels.iter().map(|&color| get_pixel(color)).collect();
+        ImageBuffer::from_pixels(self.width, self.height, pixels)
+    }
+}
+
+/// 8-bit RGB image stored row by row from the top left corner.
+pub struct ImageBuffer {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<[u8; 3]>,
+}
+
+impl ImageBuffer {
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<[u8; 3]>) -> Self {
+        assert_eq!(pixels.len(), width as usize * height as usize);
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+pub trait ImageWriter {
+    fn write(&self, frame: &Framebuffer, out: &mut dyn Write) -> io::Result<()>;
+}
+
+/// Picks a writer from the extension of `path`.
+pub fn writer_for_path(
+    path: &Path,
+    exr_precision: ExrPrecision,
+) -> Result<Box<dyn ImageWriter>, String> {
+    let extension = path
+        .extension()
+        .and_then(OsStr::to_str)
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("ppm") => Ok(Box::new(PpmWriter { binary: true })),
+        Some("png") => Ok(Box::new(PngWriter)),
+        Some("pfm") => Ok(Box::new(PfmWriter)),
+        Some("hdr") => Ok(Box::new(RgbeWriter)),
+        Some("exr") => Ok(Box::new(ExrWriter {
+            precision: exr_precision,
+        })),
+        _ => Err(format!(
+            "unsupported image format for {}, expected a .ppm, .png, .pfm, .hdr or .exr file",
+            path.display()
+        )),
+    }
+}
+
+/// Netpbm color image, binary (P6) or plain text (P3).
+pub struct PpmWriter {
+    pub binary: bool,
+}
+
+impl ImageWriter for PpmWriter {
+    fn write(&self, frame: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+        let image = frame.to_image();
+        let magic = if self.binary { "P6" } else { "P3" };
+        write!(out, "{}\n{} {}\n255\n", magic, image.width, image.height)?;
+        if self.binary {
+            out.write_all(&image.pixels.concat())
+        } else {
+            image
+                .pixels
+                .iter()
+                .try_for_each(|[r, g, b]| writeln!(out, "{} {} {}", r, g, b))
+        }
+    }
+}
+
+pub struct PngWriter;
+
+impl ImageWriter for PngWriter {
+    fn write(&self, frame: &Framebuffer, out: &mut dyn Write) -> io::Result<()> {
+        let image = frame.to_image();
+        let mut encoder = png::Encoder::new(out, image.width, image.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer
+            .write_image_data(&image.pixels.concat())
+            .map_err(io::Error::other)?;
+        writer.finish().map_err(io::Error::other)
+    }
+}
+
+/// Reads a PNG or PPM image into linear colors, undoing the gamma of `get_pixel`, or a Radiance
+/// HDR or PFM image as stored.
+pub fn read_image(path: &Path) -> io::Result<Framebuffer> {
+    let data = fs::read(path)?;
+    let extension = path
+        .extension()
+        .and_then(OsStr::to_str)
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("png") => decode_png(&data),
+        Some("ppm") => decode_ppm(&data),
+        Some("hdr") => hdr::decode_rgbe(&data),
+        Some("pfm") => hdr::decode_pfm(&data),
+        _ => Err(invalid_data(format!(
+            "unsupported image format for {}, expected a .png, .ppm, .hdr or .pfm file",
+            path.display()
+        ))),
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Inverse of the gamma 2 correction applied when writing.
+fn linear(value: u32, max_value: u32) -> f64 {
+    let v = value as f64 / max_value as f64;
+    v * v
+}
+
+fn decode_png(data: &[u8]) -> io::Result<Framebuffer> {
+    let mut decoder = png::Decoder::new(io::Cursor::new(data));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(io::Error::other)?;
+    let size = reader
+        .output_buffer_size()
+        .ok_or_else(|| invalid_data("PNG image is too large".to_string()))?;
+    let mut buffer = vec![0; size];
+    let info = reader.next_frame(&mut buffer).map_err(io::Error::other)?;
+    let channels = info.color_type.samples();
+    let pixels = buffer[..info.buffer_size()]
+        .chunks_exact(channels)
+        .map(|sample| {
+            let channel = |i: usize| linear(sample[i] as u32, 255);
+            match channels {
+                1 | 2 => Color::new(channel(0), channel(0), channel(0)),
+                _ => Color::new(channel(0), channel(1), channel(2)),
+            }
+        })
+        .collect();
+    Ok(Framebuffer::from_pixels(info.width, info.height, pixels))
+}
+
+// Binary (P6) or plain text (P3) Netpbm color image.
+fn decode_ppm(data: &[u8]) -> io::Result<Framebuffer> {
+    let mut rest = data;
+    let mut header = [0u32; 3];
+    let magic = next_token(&mut rest);
+    for value in &mut header {
+        *value = next_token(&mut rest)
+            .and_then(|token| std::str::from_utf8(token).ok()?.parse().ok())
+            .ok_or_else(|| invalid_data("malformed PPM header".to_string()))?;
+    }
+    let [width, height, max_value] = header;
+    if width == 0 || height == 0 || max_value == 0 || max_value > 65535 {
+        return Err(invalid_data("malformed PPM header".to_string()));
+    }
+    let count = width as usize * height as usize * 3;
+
+    let samples: Vec<u32> = match magic {
+        Some(b"P6") => {
+            // A single whitespace byte separates the header from the raster.
+            let raster = rest.get(1..).unwrap_or_default();
+            if max_value < 256 {
+                raster.iter().take(count).map(|&b| b as u32).collect()
+            } else {
+                raster
+                    .chunks_exact(2)
+                    .take(count)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]) as u32)
+                    .collect()
+            }
+        }
+        Some(b"P3") => std::iter::from_fn(|| next_token(&mut rest))
+            .take(count)
+            .map(|token| {
+                std::str::from_utf8(token)
+                    .ok()
+                    .and_then(|token| token.parse().ok())
+                    .ok_or_else(|| invalid_data("malformed PPM sample".to_string()))
+            })
+            .collect::<io::Result<_>>()?,
+        _ => return Err(invalid_data("not a P3 or P6 PPM image".to_string())),
+    };
+    if samples.len() < count {
+        return Err(invalid_data("PPM image is truncated".to_string()));
+    }
+    let pixels = samples
+        .chunks_exact(3)
+        .map(|rgb| {
+            let channel = |i: usize| linear(rgb[i].min(max_value), max_value);
+            Color::new(channel(0), channel(1), channel(2))
+        })
+        .collect();
+    Ok(Framebuffer::from_pixels(width, height, pixels))
+}
+
+// Next whitespace separated token, skipping `#` comments.
+pub(crate) fn next_token<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+    loop {
+        let start = data.iter().position(|b| !b.is_ascii_whitespace())?;
+        *data = &data[start..];
+        if data[0] == b'#' {
+            let end = data.iter().position(|&b| b == b'\n').unwrap_or(data.len());
+            *data = &data[end..];
+            continue;
+        }
+        let end = data
+            .iter()
+            .position(|b| b.is_ascii_whitespace())
+            .unwrap_or(data.len());
+        let (token, rest) = data.split_at(end);
+        *data = rest;
+        return Some(token);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Exactly representable after gamma correction and quantization
+    fn frame() -> Framebuffer {
+        let linear = |v: f64| (v / 256.0).powi(2);
+        let color = |r, g, b| Color::new(linear(r), linear(g), linear(b));
+        Framebuffer::from_pixels(2, 1, vec![color(255.5, 0.5, 10.5), color(1.5, 2.5, 3.5)])
+    }
+
+    #[test]
+    fn writes_binary_ppm() {
+        let mut out = Vec::new();
+        PpmWriter { binary: true }
+            .write(&frame(), &mut out)
+            .unwrap();
+        assert_eq!(out, b"P6\n2 1\n255\n\xff\x00\x0a\x01\x02\x03");
+    }
+
+    #[test]
+    fn writes_plain_ppm() {
+        let mut out = Vec::new();
+        PpmWriter { binary: false }
+            .write(&frame(), &mut out)
+            .unwrap();
+        assert_eq!(out, b"P3\n2 1\n255\n255 0 10\n1 2 3\n");
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let mut out = Vec::new();
+        PngWriter.write(&frame(), &mut out).unwrap();
+
+        let decoder = png::Decoder::new(io::Cursor::new(out));
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut data).unwrap();
+        assert_eq!(data, [255, 0, 10, 1, 2, 3]);
+    }
+
+    #[test]
+    fn reads_back_written_images() {
+        let expected = frame();
+        let same = |decoded: Framebuffer| {
+            assert_eq!((decoded.width, decoded.height), (2, 1));
+            let quantized = |c: Color| get_pixel(c);
+            assert_eq!(
+                decoded
+                    .pixels
+                    .iter()
+                    .map(|&c| quantized(c))
+                    .collect::<Vec<_>>(),
+                expected.to_image().pixels
+            );
+        };
+
+        let mut png = Vec::new();
+        PngWriter.write(&expected, &mut png).unwrap();
+        same(decode_png(&png).unwrap());
+
+        for binary in [true, false] {
+            let mut ppm = Vec::new();
+            PpmWriter { binary }.write(&expected, &mut ppm).unwrap();
+            same(decode_ppm(&ppm).unwrap());
+        }
+
+        let commented = b"P3\n# comment\n1 1 # size\n20\n20 0 5\n";
+        let decoded = decode_ppm(commented).unwrap();
+        assert_eq!(decoded.pixel(0, 0), Color::new(1.0, 0.0, 0.0625));
+        assert!(decode_ppm(b"P6\n2 2\n255\n\x00\x00").is_err());
+    }
+
+    #[test]
+    fn writer_from_extension() {
+        let writer = |path| writer_for_path(Path::new(path), ExrPrecision::Half);
+        for path in ["out.PNG", "out.ppm", "out.pfm", "out.hdr", "out.exr"] {
+            assert!(writer(path).is_ok(), "{}", path);
+        }
+        assert!(writer("out.jpg").is_err());
+        assert!(writer("out").is_err());
+    }
+}
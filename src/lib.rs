@@ -0,0 +1,65 @@
+//! Ray tracer based on Ray Tracing in One Weekend.
+//!
+//! A [`Scene`] is loaded from a TOML description with [`scene::load`] or built in code from
+//! [`Hittable`] objects and [`Material`]s. A [`Renderer`] traces it through a [`Camera`] with
+//! the given [`RenderSettings`] into a [`Film`] of per-pixel statistics:
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use wave_tracer::{bvh, scene, Renderer};
+//!
+//! let scene = scene::load(Path::new("scenes/cornell.toml")).unwrap();
+//! let camera = scene.camera.build(scene.settings.aspect_ratio());
+//! let world = bvh::build(scene.world.objects);
+//! let renderer = Renderer::new(
+//!     &*world,
+//!     &scene.lights,
+//!     &scene.background,
+//!     &camera,
+//!     scene.settings,
+//! );
+//! let image = renderer.render().to_framebuffer();
+//! ```
+
+pub mod aabb;
+pub mod bvh;
+pub mod camera;
+pub mod color;
+pub mod diffusion;
+pub mod distributed;
+pub mod environment;
+pub mod film;
+pub mod hdr;
+pub mod hittable;
+pub mod hittable_list;
+pub mod image;
+pub mod material;
+pub mod microfacet;
+pub mod obj;
+pub mod onb;
+pub mod pdf;
+pub mod perlin;
+pub mod polarization;
+mod progress;
+pub mod random_scene;
+pub mod ray;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod settings;
+pub mod spectrum;
+pub mod sphere;
+pub mod texture;
+pub mod tiles;
+pub mod triangle;
+mod util;
+pub mod vec3;
+mod wire;
+
+pub use camera::Camera;
+pub use film::Film;
+pub use hittable::Hittable;
+pub use material::Material;
+pub use render::Renderer;
+pub use scene::Scene;
+pub use settings::{CameraSettings, RenderSettings};
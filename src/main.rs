@@ -1,181 +1,299 @@
-mod camera;
-mod color;
-mod diffusion;
-mod hittable;
-mod hittable_list;
-mod material;
-mod ray;
-mod sphere;
-mod util;
-mod vec3;
-use crate::camera::Camera;
-use crate::color::get_pixel;
-use crate::color::Color;
-use crate::diffusion::{random_in_unit_sphere, random_unit_vector};
-use crate::hittable::Hittable;
-use crate::hittable_list::HittableList;
-use crate::material::Material;
-use crate::material::{Dielectric, Lambertian, Metal};
-use crate::ray::Ray;
-use crate::sphere::Sphere;
-use crate::vec3::{Point3, Vec3};
-use rand::distributions::{Distribution, Standard, Uniform};
+mod cli;
+
+use crate::cli::Args;
+use clap::Parser;
 use rand::{thread_rng, Rng};
-use rayon::prelude::*;
-use std::sync::{Arc, Mutex};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use wave_tracer::distributed::{self, Coordinator, Job};
+use wave_tracer::hdr::ExrPrecision;
+use wave_tracer::image::{self, Framebuffer, ImageWriter, PpmWriter};
+use wave_tracer::tiles;
+use wave_tracer::{bvh, film, random_scene, scene, Film, Renderer};
 
-fn ray_color(r: &Ray, world: &impl Hittable, depth: usize) -> Color {
-    if depth == 0 {
-        return Color::new(0.0, 0.0, 0.0);
-    }
-    if let Some(rec) = world.hit(r, 0.001, f64::INFINITY) {
-        if let Some(scatter) = rec.material.scatter(r, &rec) {
-            return scatter.attenuation * ray_color(&scatter.scattered, world, depth - 1);
-        }
-        return Color::new(0.0, 0.0, 0.0);
-    }
-    let unit_direction = r.direction.unit_vector();
-    let t = 0.5 * (unit_direction.y + 1.0);
-    return (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0);
-}
+/// Set by Ctrl-C while a checkpoint is being kept, to stop sampling and save it.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
 
-fn random_scene() -> HittableList {
-    let mut objects: Vec<Box<dyn Hittable>> = Vec::new();
+/// Samples per pixel between checkpoints when `--progressive` does not set the pass size.
+const CHECKPOINT_PASS_SAMPLES: usize = 16;
 
-    let mut rng = thread_rng();
-    let metal_between = Uniform::from(0.5..1.0);
+struct Output {
+    path: Option<PathBuf>,
+    writer: Box<dyn ImageWriter>,
+}
 
-    let ground_material = Lambertian {
-        albedo: Color::new(0.5, 0.5, 0.5),
-    };
-    objects.push(Box::new(Sphere {
-        center: Point3::new(0.0, -1000.0, 0.0),
-        radius: 1000.0,
-        material: Box::new(ground_material),
-    }));
-
-    let p = Point3::new(4.0, 0.2, 0.0);
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let (choose_mat, x, z) = rng.gen::<(f64, f64, f64)>();
-            let center = Point3::new(a as f64 + 0.9 * x, 0.2, b as f64 + 0.9 * z);
-
-            if (center - p).length() > 0.9 {
-                let sphere_material: Box<dyn Material> = if choose_mat < 0.8 {
-                    let (r1, g1, b1, r2, g2, b2) = rng.gen();
-                    // diffuse
-                    let albedo = Color::new(r1, g1, b1) * Color::new(r2, g2, b2);
-                    Box::new(Lambertian { albedo })
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let albedo = Color::new(
-                        metal_between.sample(&mut rng),
-                        metal_between.sample(&mut rng),
-                        metal_between.sample(&mut rng),
-                    );
-                    let fuzz = rng.gen_range(0.5..1.0);
-                    Box::new(Metal::new(albedo, fuzz))
-                } else {
-                    //glass
-                    Box::new(Dielectric { ir: 1.5 })
-                };
-                objects.push(Box::new(Sphere {
-                    center,
-                    radius: 0.2,
-                    material: sphere_material,
-                }));
+impl Output {
+    // Plain text PPM on stdout unless an output file is given.
+    fn open(path: Option<&Path>, exr_precision: ExrPrecision) -> Result<Self, String> {
+        match path {
+            Some(path) if path != Path::new("-") => {
+                let writer = image::writer_for_path(path, exr_precision)?;
+                // Fail before rendering rather than after, but leave an existing image alone
+                // until it is replaced.
+                OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(path)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+                Ok(Self {
+                    path: Some(path.to_path_buf()),
+                    writer,
+                })
             }
+            _ => Ok(Self {
+                path: None,
+                writer: Box::new(PpmWriter { binary: false }),
+            }),
         }
     }
 
-    objects.push(Box::new(Sphere {
-        center: Point3::new(0.0, 1.0, 0.0),
-        radius: 1.0,
-        material: Box::new(Dielectric { ir: 1.5 }),
-    }));
-
-    objects.push(Box::new(Sphere {
-        center: Point3::new(-4.0, 1.0, 0.0),
-        radius: 1.0,
-        material: Box::new(Lambertian {
-            albedo: Color::new(0.4, 0.2, 0.1),
-        }),
-    }));
+    /// Files are written next to their destination and renamed over it, so that an image
+    /// viewer watching the file never sees it half written.
+    fn write(&self, frame: &Framebuffer) -> io::Result<()> {
+        match &self.path {
+            Some(path) => replace_file(path, |sink| self.writer.write(frame, sink)),
+            None => {
+                let mut sink = BufWriter::new(io::stdout());
+                self.writer.write(frame, &mut sink)?;
+                sink.flush()
+            }
+        }
+    }
+}
 
-    objects.push(Box::new(Sphere {
-        center: Point3::new(4.0, 1.0, 0.0),
-        radius: 1.0,
-        material: Box::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0)),
-    }));
+/// Writes an image besides the render, exiting on failure.
+fn write_aov(path: &Path, image: &Framebuffer, name: &str, args: &Args) {
+    let written = Output::open(Some(path), args.exr_precision.into()).and_then(|output| {
+        output
+            .write(image)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    });
+    written.unwrap_or_else(|e| {
+        eprintln!("Failed to write {} {}", name, e);
+        process::exit(1);
+    });
+}
 
-    HittableList { objects }
+/// Writes a file next to `path` and renames it over `path` once complete.
+fn replace_file(
+    path: &Path,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    let temporary = path.with_file_name(name);
+    let mut sink = BufWriter::new(File::create(&temporary)?);
+    write(&mut sink)?;
+    sink.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&temporary, path)
 }
 
 fn main() {
-    // Image
-    const ASPECT_RATIO: f64 = 3.0 / 2.0;
-    const IMAGE_WIDTH: f64 = 1200.0;
-    const IMAGE_HEIGHT: u32 = (IMAGE_WIDTH / ASPECT_RATIO) as u32;
-    const SAMPLES_PER_PIXEL: usize = 500;
-    const MAX_DEPTH: usize = 50;
+    let args = Args::parse();
+
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads as usize)
+            .build_global()
+            .expect("Failed to configure the render thread pool");
+    }
+
+    if let Some(address) = &args.worker {
+        eprintln!("Rendering for {}", address);
+        distributed::work(address.as_str()).unwrap_or_else(|e| {
+            eprintln!("Worker stopped: {}", e);
+            process::exit(1);
+        });
+        eprintln!("Done.");
+        return;
+    }
+
+    // World, with the canonical path and source of its file, which workers and checkpoints use
+    // to tell it from others.
+    let scene_file = args.scene.as_ref().map(|path| {
+        let source = fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("Cannot read scene {}: {}", path.display(), e);
+            process::exit(1);
+        });
+        (path, source)
+    });
+    let scene = match &scene_file {
+        Some((path, source)) => scene::parse(source, path).unwrap_or_else(|e| {
+            eprintln!("Cannot load scene {}", e);
+            process::exit(1);
+        }),
+        None => {
+            let seed = args.seed.unwrap_or_else(|| thread_rng().gen());
+            eprintln!("Seed: {}", seed);
+            random_scene::default_scene(seed)
+        }
+    };
+    let scene_file = scene_file.map(|(path, source)| {
+        (
+            fs::canonicalize(path).unwrap_or_else(|_| path.clone()),
+            source,
+        )
+    });
+    let world = bvh::build(scene.world.objects);
+    let settings = args
+        .render_settings(scene.settings)
+        .unwrap_or_else(|e| e.exit());
+    let camera_settings = args
+        .camera_settings(scene.camera)
+        .unwrap_or_else(|e| e.exit());
 
-    // World
+    let preview_path = args.preview_path().unwrap_or_else(|e| e.exit());
 
-    let world = random_scene();
+    let output =
+        Output::open(args.output.as_deref(), args.exr_precision.into()).unwrap_or_else(|e| {
+            eprintln!("Cannot open output {}", e);
+            process::exit(1);
+        });
 
     // Camera
-    let look_from = Point3::new(13.0, 2.0, 3.0);
-    let look_at = Point3::new(0.0, 0.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0;
-    let aperture = 0.1;
-
-    let cam = Camera::new(
-        look_from,
-        look_at,
-        vup,
-        20.0,
-        ASPECT_RATIO,
-        aperture,
-        dist_to_focus,
-    );
+    let cam = camera_settings.build(settings.aspect_ratio());
 
     // Render
+    let background = args.background.clone().unwrap_or(scene.background);
+    let renderer = Renderer::new(&*world, &scene.lights, &background, &cam, settings)
+        .interrupted_by(&INTERRUPTED)
+        .with_progress();
+    // Random scenes are told apart by the seed in the settings, scene files by their contents.
+    let scene_hash = scene_file
+        .as_ref()
+        .map(|(path, source)| (path, film::fingerprint_hash(source.as_bytes())));
+    let fingerprint = format!(
+        "{:?} {:?} {:?} {:?}",
+        settings, camera_settings, background, scene_hash
+    );
+    let mut film = match (&args.checkpoint, args.resume) {
+        (Some(path), true) => File::open(path)
+            .and_then(|file| Film::read_checkpoint(&fingerprint, &mut BufReader::new(file)))
+            .unwrap_or_else(|e| {
+                eprintln!("Cannot resume from {}: {}", path.display(), e);
+                process::exit(1);
+            }),
+        _ => Film::new(settings.image_width, settings.image_height),
+    };
+    if let Some(path) = &args.checkpoint {
+        ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::Relaxed)).unwrap_or_else(|e| {
+            eprintln!("Cannot catch Ctrl-C: {}", e);
+            process::exit(1);
+        });
+        eprintln!("Saving progress to {}", path.display());
+    }
+    let preview = preview_path.map(|path| {
+        Output::open(Some(path), args.exr_precision.into()).unwrap_or_else(|e| {
+            eprintln!("Cannot open preview {}", e);
+            process::exit(1);
+        })
+    });
 
-    println!("P3\n{} {}\n255", IMAGE_WIDTH, IMAGE_HEIGHT);
-    let scanlines = Arc::new(Mutex::new(IMAGE_HEIGHT));
-
-    let image: String = (0..IMAGE_HEIGHT)
-        .into_par_iter()
-        .rev()
-        .map(|j| {
-            let line: String = (0..IMAGE_WIDTH as u32)
-                .into_par_iter()
-                .map(|i| {
-                    let pixel_color = thread_rng()
-                        .sample_iter::<(f64, f64), &Standard>(&Standard)
-                        .take(SAMPLES_PER_PIXEL)
-                        .map(|(ir, ij)| {
-                            let u = (i as f64 + ir) / (IMAGE_WIDTH - 1.0);
-                            let v = (j as f64 + ij) / (IMAGE_HEIGHT as f64 - 1.0);
-                            let r = cam.get_ray(u, v);
-                            ray_color(&r, &world, MAX_DEPTH)
-                        })
-                        .fold(Color::new(0.0, 0.0, 0.0), |acc, c| acc + c);
-
-                    let pixel = get_pixel(pixel_color, SAMPLES_PER_PIXEL);
-                    pixel
-                })
-                .collect();
-            let scanlines = Arc::clone(&scanlines);
-            let mut scanline = scanlines.lock().unwrap();
-            *scanline -= 1;
-            eprint!("\rScanlines remaining: {} ", scanline);
-            line
+    let pass_samples = match (args.progressive, &args.checkpoint) {
+        (Some(pass_samples), _) => pass_samples as usize,
+        (None, Some(_)) => CHECKPOINT_PASS_SAMPLES,
+        (None, None) => settings.samples_per_pixel,
+    };
+    let passes = settings.samples_per_pixel.div_ceil(pass_samples);
+    let tiles = tiles::tiles(
+        settings.image_width,
+        settings.image_height,
+        args.tile_size,
+        args.tile_order.into(),
+    );
+    let coordinator = args.serve.as_ref().map(|address| {
+        let job = Job {
+            scene: scene_file.clone(),
+            settings,
+            camera: camera_settings,
+            background: background.clone(),
+        };
+        let coordinator = TcpListener::bind(address.as_str())
+            .and_then(|listener| Coordinator::new(listener, &job))
+            .map(|coordinator| {
+                let coordinator = coordinator.interrupted_by(&INTERRUPTED).with_progress();
+                match args.tile_timeout {
+                    Some(seconds) => coordinator.with_tile_timeout(Duration::from_secs(seconds)),
+                    None => coordinator,
+                }
+            });
+        eprintln!("Waiting for workers on {}", address);
+        coordinator.unwrap_or_else(|e| {
+            eprintln!("Cannot serve on {}: {}", address, e);
+            process::exit(1);
         })
-        .collect();
+    });
+    let done = film
+        .pixels
+        .iter()
+        .map(|stats| stats.count as usize)
+        .max()
+        .unwrap_or(0);
+    for pass in done / pass_samples + 1..=passes {
+        let samples = (pass * pass_samples).min(settings.samples_per_pixel);
+        match &coordinator {
+            Some(coordinator) => coordinator.render_pass(&mut film, &tiles, samples),
+            None => renderer.render_pass(&mut film, &tiles, samples),
+        }
+        if let Some(path) = &args.checkpoint {
+            replace_file(path, |sink| film.write_checkpoint(&fingerprint, sink)).unwrap_or_else(
+                |e| {
+                    eprintln!("Failed to write checkpoint {}: {}", path.display(), e);
+                    process::exit(1);
+                },
+            );
+        }
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            eprintln!("\nInterrupted, continue with --resume");
+            process::exit(130);
+        }
+        if passes > 1 {
+            eprint!("\rPass {} of {} done ", pass, passes);
+        }
+        if let Some(preview) = &preview {
+            preview.write(&film.to_framebuffer()).unwrap_or_else(|e| {
+                eprintln!("Failed to write preview: {}", e);
+                process::exit(1);
+            });
+        }
+    }
 
-    print!("{}", image);
+    output.write(&film.to_framebuffer()).unwrap_or_else(|e| {
+        eprintln!("Failed to write image: {}", e);
+        process::exit(1);
+    });
+    if settings.noise_threshold > 0.0 {
+        let samples: u64 = film.pixels.iter().map(|stats| stats.count).sum();
+        eprintln!(
+            "\nAverage samples per pixel: {:.1}",
+            samples as f64 / film.pixels.len() as f64
+        );
+    }
+    if let Some(path) = &args.spp_heatmap {
+        let heatmap = film.sample_heatmap(settings.samples_per_pixel);
+        write_aov(path, &heatmap, "sample heatmap", &args);
+    }
+    if let Some(path) = &args.dolp {
+        write_aov(
+            path,
+            &film.polarization_degree(),
+            "degree of polarization",
+            &args,
+        );
+    }
+    if let Some(path) = &args.aolp {
+        write_aov(
+            path,
+            &film.polarization_angle(),
+            "angle of polarization",
+            &args,
+        );
+    }
     eprintln!("Done.");
 }
@@ -1,55 +1,99 @@
+use crate::color::Color;
+use crate::diffusion::random_in_unit_sphere;
 use crate::hittable::HitRecord;
-use crate::random_in_unit_sphere;
-use crate::random_unit_vector;
-use crate::Color;
-use crate::Ray;
-use crate::Vec3;
+use crate::microfacet::{Ggx, RoughSurface};
+use crate::pdf::{CosinePdf, Pdf};
+use crate::polarization::{
+    fresnel_reflection, fresnel_transmission, incidence_frame, unpolarized_reflectance, Complex,
+    Mueller, Polarizing,
+};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::texture::{SolidColor, Texture};
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
 use std::fmt::Debug;
+use std::sync::Arc;
 
-pub struct Scatter {
-    pub attenuation: Color,
-    pub scattered: Ray,
+pub enum Scatter {
+    /// A single outgoing ray, chosen without a density that lights could be weighed against.
+    Specular { attenuation: Color, scattered: Ray },
+    /// Outgoing directions drawn from `pdf` and weighted by `Material::eval`.
+    Sampled { pdf: Box<dyn Pdf> },
 }
 
-pub trait Material: Sync {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter>;
+pub trait Material: Send + Sync {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter>;
+
+    /// BSDF times the cosine to the normal for light leaving along `direction`, needed by
+    /// materials that return `Scatter::Sampled`.
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    /// Light given off at the hit point, black for materials that are not light sources.
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    /// Whether objects made of the material should be sampled as lights.
+    fn is_light(&self) -> bool {
+        false
+    }
+
+    /// How scattering light from `direction` towards the origin of `r_in` changes its
+    /// polarization, on polarized paths. The Mueller matrix passes unpolarized light at full
+    /// intensity, which `scatter` and `eval` already account for. `None` depolarizes the light
+    /// like a diffuse surface.
+    fn polarization(&self, _r_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> Option<Polarizing> {
+        None
+    }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Clone)]
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture>,
 }
 
-impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
-        let scatter_direction = rec.normal + random_unit_vector();
+impl Lambertian {
+    pub fn new(albedo: Color) -> Self {
+        Self::textured(Arc::new(SolidColor { color: albedo }))
+    }
 
-        // Catch degenerate scatter direction
-        let corrected_scatter_direction = if scatter_direction.near_zero() {
-            rec.normal
-        } else {
-            scatter_direction
-        };
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
+        Self { albedo }
+    }
+}
 
-        Some(Scatter {
-            scattered: Ray::new(rec.p, corrected_scatter_direction),
-            attenuation: self.albedo,
+impl Material for Lambertian {
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, _sampler: &mut dyn Sampler) -> Option<Scatter> {
+        Some(Scatter::Sampled {
+            pdf: Box::new(CosinePdf::new(rec.normal)),
         })
     }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Color {
+        let cosine = rec.normal.dot(&direction.unit_vector()).max(0.0);
+        r_in.spectrum(self.albedo.value(rec.u, rec.v, &rec.p) * (cosine / PI))
+    }
 }
 
 fn reflect(v: Vec3, n: Vec3) -> Vec3 {
     v - 2.0 * v.dot(&n) * n
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Clone)]
 pub struct Metal {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
     fuzz: f64,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Self {
+        Self::textured(Arc::new(SolidColor { color: albedo }), fuzz)
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>, fuzz: f64) -> Self {
         Self {
             albedo,
             fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
@@ -58,19 +102,59 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
         let reflected = reflect(r_in.direction.unit_vector(), rec.normal);
-        let scattered = Ray::new(rec.p, reflected + self.fuzz * random_in_unit_sphere());
+        let scattered = r_in.spawn(
+            rec.p,
+            reflected + self.fuzz * random_in_unit_sphere(sampler),
+        );
 
         if scattered.direction.dot(&rec.normal) > 0.0 {
-            Some(Scatter {
+            Some(Scatter::Specular {
                 scattered,
-                attenuation: self.albedo,
+                attenuation: r_in.spectrum(self.albedo.value(rec.u, rec.v, &rec.p)),
             })
         } else {
             None
         }
     }
+
+    fn polarization(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<Polarizing> {
+        let albedo = r_in.spectrum(self.albedo.value(rec.u, rec.v, &rec.p));
+        let iors = [albedo.r, albedo.g, albedo.b].map(conductor_ior);
+        Some(conductor_polarization(r_in, rec, direction, iors))
+    }
+}
+
+// Reflection off a metal with complex index of refraction `iors` at each channel, from the facet
+// halfway between the incident direction and `direction`.
+fn conductor_polarization(
+    r_in: &Ray,
+    rec: &HitRecord,
+    direction: Vec3,
+    iors: [Complex; 3],
+) -> Polarizing {
+    let incident = r_in.direction.unit_vector();
+    let facet = (direction.unit_vector() - incident).unit_vector();
+    let cos_i = (-incident).dot(&facet);
+    let channels = iors.map(|eta| {
+        let (r_s, r_p) = fresnel_reflection(cos_i, eta);
+        Mueller::reflection(r_s, r_p)
+    });
+    Polarizing {
+        mueller: Mueller::per_channel(channels),
+        frame: incidence_frame(incident, direction, rec.normal),
+    }
+}
+
+/// Complex index of refraction of a metal that reflects `reflectance` of the light head on,
+/// after Gulbrandsen 2014 with the edge tint equal to the reflectance.
+fn conductor_ior(reflectance: f64) -> Complex {
+    let r = reflectance.clamp(0.0, 0.99);
+    let sqrt_r = r.sqrt();
+    let n = r * (1.0 - r) / (1.0 + r) + (1.0 - r) * (1.0 + sqrt_r) / (1.0 - sqrt_r);
+    let k2 = (r * (n + 1.0) * (n + 1.0) - (n - 1.0) * (n - 1.0)) / (1.0 - r);
+    Complex::new(n, k2.max(0.0).sqrt())
 }
 
 fn refract(uv: Vec3, n: Vec3, etai_over_etat: f64) -> Vec3 {
@@ -80,12 +164,82 @@ fn refract(uv: Vec3, n: Vec3, etai_over_etat: f64) -> Vec3 {
     r_out_perp + r_out_parallel
 }
 
+/// Wavelength at which the index of refraction of a dispersive material is quoted, the helium d
+/// line in nanometers. RGB paths refract at it.
+pub const D_LINE: f64 = 587.56;
+// Hydrogen F and C lines, between which the Abbe number measures the dispersion.
+const F_LINE: f64 = 486.13;
+const C_LINE: f64 = 656.27;
+
+/// Index of refraction as a function of the wavelength in nanometers.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Ior {
+    Constant(f64),
+    /// `n = a + b / λ²` with λ in micrometers.
+    Cauchy {
+        a: f64,
+        b: f64,
+    },
+    /// `n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)` with λ in micrometers and cᵢ in square micrometers, the
+    /// form glass catalogs list measured indices in.
+    Sellmeier {
+        b: [f64; 3],
+        c: [f64; 3],
+    },
+}
+
+impl Ior {
+    /// Cauchy curve through `n_d` at the d line with Abbe number `v_d = (n_d - 1) / (n_F - n_C)`,
+    /// the two numbers glass catalogs lead with. Lower Abbe numbers disperse more: about 64 for
+    /// crown glass, 30 for flint and 55 for diamond.
+    pub fn from_abbe(n_d: f64, v_d: f64) -> Self {
+        let inverse_square = |wavelength: f64| (1000.0 / wavelength).powi(2);
+        let b = (n_d - 1.0) / (v_d * (inverse_square(F_LINE) - inverse_square(C_LINE)));
+        Ior::Cauchy {
+            a: n_d - b * inverse_square(D_LINE),
+            b,
+        }
+    }
+
+    pub fn at(&self, wavelength: f64) -> f64 {
+        let micrometers = wavelength / 1000.0;
+        let l2 = micrometers * micrometers;
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => (1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>())
+                .max(0.0)
+                .sqrt(),
+        }
+    }
+
+    /// Whether different wavelengths refract differently.
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Dielectric {
-    pub ir: f64, // Index of Refraction
+    pub ior: Ior,
 }
 
 impl Dielectric {
+    /// Glass with the same index of refraction at every wavelength.
+    pub fn new(ir: f64) -> Self {
+        Self {
+            ior: Ior::Constant(ir),
+        }
+    }
+
+    // Index of refraction on the path of `r_in`: at its hero wavelength if the glass disperses.
+    fn index_for(&self, r_in: &Ray) -> f64 {
+        match &r_in.wavelengths {
+            Some(wavelengths) if self.ior.is_dispersive() => self.ior.at(wavelengths.hero()),
+            _ => self.ior.at(D_LINE),
+        }
+    }
+
     fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
         // Use Schlick's approximation for reflectance.
         let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
@@ -95,33 +249,571 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
-        let attenuation = Color::new(1.0, 1.0, 1.0);
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        // Each wavelength of a spectral path would refract its own way, so the hero picks the
+        // direction and the others are dropped.
+        let ir = self.index_for(r_in);
+        let mut wavelengths = r_in.wavelengths;
+        let attenuation = match &mut wavelengths {
+            Some(wavelengths) if self.ior.is_dispersive() => wavelengths.hero_only(),
+            _ => Color::new(1.0, 1.0, 1.0),
         };
+        let refraction_ratio = if rec.front_face { 1.0 / ir } else { ir };
 
         let unit_direction = r_in.direction.unit_vector();
         let cos_theta = (-unit_direction).dot(&rec.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refact = refraction_ratio * sin_theta > 1.0;
+        // Drawn even under total internal reflection, to keep the dimensions of the path in step.
+        let u = sampler.get_1d();
+
+        // Polarized paths weigh reflection and refraction by the exact Fresnel equations, like
+        // the Mueller matrices of `polarization`, which only carry the polarization state.
+        let reflectance = if r_in.polarized {
+            unpolarized_reflectance(cos_theta, (1.0 / refraction_ratio).into())
+        } else {
+            Dielectric::reflectance(cos_theta, refraction_ratio)
+        };
+        let reflects = cannot_refact || reflectance > u;
 
-        let direction = if cannot_refact
-            || Dielectric::reflectance(cos_theta, refraction_ratio) > rand::random()
-        {
+        let direction = if reflects {
             reflect(unit_direction, rec.normal)
         } else {
             refract(unit_direction, rec.normal, refraction_ratio)
         };
 
-        let scattered = Ray::new(rec.p, direction);
+        let scattered = Ray {
+            wavelengths,
+            ..r_in.spawn(rec.p, direction)
+        };
 
-        Some(Scatter {
+        Some(Scatter::Specular {
             scattered,
             attenuation,
         })
     }
+
+    fn polarization(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<Polarizing> {
+        let ir = self.index_for(r_in);
+        let eta = if rec.front_face { ir } else { 1.0 / ir };
+        let cos_i = -r_in.direction.unit_vector().dot(&rec.normal);
+        let reflected = direction.dot(&rec.normal) > 0.0;
+        Some(dielectric_polarization(
+            r_in, rec, direction, cos_i, eta, reflected,
+        ))
+    }
+}
+
+// Reflection or refraction at `cosine` to the normal of the facet, with relative index of
+// refraction `eta`.
+fn dielectric_polarization(
+    r_in: &Ray,
+    rec: &HitRecord,
+    direction: Vec3,
+    cosine: f64,
+    eta: f64,
+    reflected: bool,
+) -> Polarizing {
+    let mueller = if reflected {
+        let (r_s, r_p) = fresnel_reflection(cosine, eta.into());
+        Mueller::reflection(r_s, r_p)
+    } else {
+        let (t_s, t_p) = fresnel_transmission(cosine, eta);
+        Mueller::transmission(t_s, t_p)
+    };
+    let incident = r_in.direction.unit_vector();
+    Polarizing {
+        mueller: Mueller::uniform(mueller),
+        frame: incidence_frame(incident, direction, rec.normal),
+    }
+}
+
+/// Metal as a rough surface of GGX microfacets, reflecting per the Fresnel equations of its
+/// complex index of refraction `eta + ik` at the red, green and blue channels. Unlike the fuzz of
+/// [`Metal`], the microfacets keep grazing reflections bright and never reflect more light than
+/// arrives. Spectral paths upsample the reflectance of the channels.
+#[derive(Debug, Copy, Clone)]
+pub struct Conductor {
+    pub eta: Color,
+    pub k: Color,
+    pub ggx: Ggx,
+}
+
+impl Conductor {
+    /// Metal with a perceptual `roughness` from 0 for a mirror to 1, see [`Ggx::from_roughness`].
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Self {
+        Self {
+            eta,
+            k,
+            ggx: Ggx::from_roughness(roughness),
+        }
+    }
+
+    fn iors(&self) -> [Complex; 3] {
+        [
+            Complex::new(self.eta.r, self.k.r),
+            Complex::new(self.eta.g, self.k.g),
+            Complex::new(self.eta.b, self.k.b),
+        ]
+    }
+
+    fn fresnel(&self, r_in: &Ray, cosine: f64) -> Color {
+        let [r, g, b] = self.iors().map(|eta| unpolarized_reflectance(cosine, eta));
+        r_in.spectrum(Color::new(r, g, b))
+    }
+
+    fn surface(&self, r_in: &Ray, rec: &HitRecord) -> RoughSurface {
+        RoughSurface::new(rec.normal, r_in.direction, self.ggx, None)
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, _sampler: &mut dyn Sampler) -> Option<Scatter> {
+        if !self.ggx.is_smooth() {
+            return Some(Scatter::Sampled {
+                pdf: Box::new(self.surface(r_in, rec)),
+            });
+        }
+        let unit_direction = r_in.direction.unit_vector();
+        let cos_theta = (-unit_direction).dot(&rec.normal);
+        Some(Scatter::Specular {
+            attenuation: self.fresnel(r_in, cos_theta),
+            scattered: r_in.spawn(rec.p, reflect(unit_direction, rec.normal)),
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Color {
+        match self.surface(r_in, rec).facet(direction) {
+            Some(facet) => self.fresnel(r_in, facet.cosine) * facet.bsdf_cos,
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn polarization(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<Polarizing> {
+        Some(conductor_polarization(r_in, rec, direction, self.iors()))
+    }
+}
+
+/// Glass with a rough surface of GGX microfacets, like frosted or etched glass, that reflects and
+/// refracts per the exact Fresnel equations. Smooth at a roughness of 0.
+#[derive(Debug, Copy, Clone)]
+pub struct RoughDielectric {
+    pub ir: f64,
+    pub ggx: Ggx,
+}
+
+impl RoughDielectric {
+    /// Glass of index `ir` with a perceptual `roughness` from 0 to 1, see
+    /// [`Ggx::from_roughness`].
+    pub fn new(ir: f64, roughness: f64) -> Self {
+        Self {
+            ir,
+            ggx: Ggx::from_roughness(roughness),
+        }
+    }
+
+    // Index of refraction past the surface over that before it.
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            self.ir
+        } else {
+            1.0 / self.ir
+        }
+    }
+
+    fn surface(&self, r_in: &Ray, rec: &HitRecord) -> RoughSurface {
+        RoughSurface::new(rec.normal, r_in.direction, self.ggx, Some(self.eta(rec)))
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        if !self.ggx.is_smooth() {
+            return Some(Scatter::Sampled {
+                pdf: Box::new(self.surface(r_in, rec)),
+            });
+        }
+        let eta = self.eta(rec);
+        let unit_direction = r_in.direction.unit_vector();
+        let cos_theta = (-unit_direction).dot(&rec.normal);
+        let direction = if sampler.get_1d() < unpolarized_reflectance(cos_theta, eta.into()) {
+            reflect(unit_direction, rec.normal)
+        } else {
+            refract(unit_direction, rec.normal, 1.0 / eta)
+        };
+        Some(Scatter::Specular {
+            attenuation: Color::new(1.0, 1.0, 1.0),
+            scattered: r_in.spawn(rec.p, direction),
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Color {
+        let surface = self.surface(r_in, rec);
+        match surface.facet(direction) {
+            Some(facet) => {
+                let f = surface.fraction(&facet) * facet.bsdf_cos;
+                Color::new(f, f, f)
+            }
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn polarization(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<Polarizing> {
+        let facet = self.surface(r_in, rec).facet(direction)?;
+        Some(dielectric_polarization(
+            r_in,
+            rec,
+            direction,
+            facet.cosine,
+            self.eta(rec),
+            facet.reflected,
+        ))
+    }
+}
+
+/// Wavelengths in nanometers that stand in for the red, green and blue channels of RGB paths in
+/// wave optics effects.
+pub const RGB_WAVELENGTHS: [f64; 3] = [630.0, 532.0, 465.0];
+
+/// Transparent film a few hundred nanometers thick, like soap or oil, over a `base` material.
+/// Light reflected off the top of the film interferes with light reflected off the bottom, so
+/// the film reflects some wavelengths much more than others depending on its thickness and the
+/// viewing angle. The rest of the light reaches the base.
+pub struct ThinFilm {
+    pub base: Arc<dyn Material>,
+    /// Thickness in nanometers.
+    pub thickness: f64,
+    pub ior: f64,
+    /// Index of refraction of the medium under the film, 1 for a soap bubble.
+    pub base_ior: f64,
+}
+
+impl ThinFilm {
+    /// Reflectance of the film at `wavelength` nanometers, for light arriving at `cosine` to the
+    /// normal from a medium of index `above` with `below` on the other side of the film.
+    fn film_reflectance(&self, cosine: f64, above: f64, below: f64, wavelength: f64) -> f64 {
+        // Amplitudes from Schlick's reflectance, negative when reflecting off a denser medium,
+        // which shifts the phase by half a wave.
+        let amplitude = |cosine: f64, n1: f64, n2: f64| {
+            let r = Dielectric::reflectance(cosine, n1 / n2).sqrt();
+            if n1 < n2 {
+                -r
+            } else {
+                r
+            }
+        };
+        let sin2_film = (above / self.ior).powi(2) * (1.0 - cosine * cosine);
+        if sin2_film >= 1.0 {
+            return 1.0;
+        }
+        let cos_film = (1.0 - sin2_film).sqrt();
+        let top = amplitude(cosine, above, self.ior);
+        let bottom = if (self.ior / below).powi(2) * sin2_film >= 1.0 {
+            1.0
+        } else {
+            amplitude(cos_film, self.ior, below)
+        };
+        // Sum of the waves bouncing back and forth inside the film, each a round trip behind.
+        let phase = 4.0 * PI * self.ior * self.thickness * cos_film / wavelength;
+        let cross = 2.0 * top * bottom * phase.cos();
+        let reflected = top * top + bottom * bottom + cross;
+        let total = 1.0 + top * top * bottom * bottom + cross;
+        (reflected / total).clamp(0.0, 1.0)
+    }
+
+    /// Reflectance at the wavelengths of the path, or at `RGB_WAVELENGTHS`.
+    fn reflectance(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        let cosine = (-r_in.direction.unit_vector()).dot(&rec.normal).min(1.0);
+        let (above, below) = if rec.front_face {
+            (1.0, self.base_ior)
+        } else {
+            (self.base_ior, 1.0)
+        };
+        let wavelengths = r_in.wavelengths.map_or(RGB_WAVELENGTHS, |w| w.lambda);
+        let [r, g, b] =
+            wavelengths.map(|lambda| self.film_reflectance(cosine, above, below, lambda));
+        Color::new(r, g, b)
+    }
+
+    // Chance of reflecting off the film rather than reaching the base.
+    fn reflect_probability(reflectance: Color) -> f64 {
+        (reflectance.r + reflectance.g + reflectance.b) / 3.0
+    }
+
+    // Light passed on to the base, given that the path did not reflect off the film.
+    fn transmitted(reflectance: Color) -> Color {
+        let p = Self::reflect_probability(reflectance);
+        (Color::new(1.0, 1.0, 1.0) - reflectance) * (1.0 / (1.0 - p))
+    }
+}
+
+impl Material for ThinFilm {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        let reflectance = self.reflectance(r_in, rec);
+        let p = Self::reflect_probability(reflectance);
+        let u = sampler.get_1d();
+        if p >= 1.0 || u < p {
+            let reflected = reflect(r_in.direction.unit_vector(), rec.normal);
+            return Some(Scatter::Specular {
+                attenuation: reflectance * (1.0 / p),
+                scattered: r_in.spawn(rec.p, reflected),
+            });
+        }
+        match self.base.scatter(r_in, rec, sampler)? {
+            Scatter::Specular {
+                attenuation,
+                scattered,
+            } => Some(Scatter::Specular {
+                attenuation: attenuation * Self::transmitted(reflectance),
+                scattered,
+            }),
+            sampled => Some(sampled),
+        }
+    }
+
+    // Only asked for when the base was sampled, which the film let through.
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Color {
+        self.base.eval(r_in, rec, direction) * Self::transmitted(self.reflectance(r_in, rec))
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        self.base.emitted(r_in, rec)
+    }
+
+    // Only the base polarizes; the interference in the film is taken to be the same for both.
+    fn polarization(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<Polarizing> {
+        self.base.polarization(r_in, rec, direction)
+    }
+}
+
+/// Ideal linear polarizing filter, like the sheet of a polarizer in front of a lens. Light passes
+/// straight through, polarized along `axis` as seen from its direction, and loses half its
+/// intensity if it was unpolarized. Paths that are not polarized only see the loss.
+#[derive(Debug, Copy, Clone)]
+pub struct LinearPolarizer {
+    pub axis: Vec3,
+}
+
+impl Material for LinearPolarizer {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, _sampler: &mut dyn Sampler) -> Option<Scatter> {
+        Some(Scatter::Specular {
+            attenuation: Color::new(0.5, 0.5, 0.5),
+            scattered: r_in.spawn(rec.p, r_in.direction),
+        })
+    }
+
+    fn polarization(&self, _r_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> Option<Polarizing> {
+        Some(Polarizing {
+            mueller: Mueller::uniform(Mueller::LINEAR_POLARIZER),
+            frame: self.axis,
+        })
+    }
+}
+
+/// Light source that emits the same radiance in all directions from both sides of a surface.
+#[derive(Debug, Copy, Clone)]
+pub struct DiffuseLight {
+    pub emit: Color,
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<Scatter> {
+        None
+    }
+
+    fn emitted(&self, r_in: &Ray, _rec: &HitRecord) -> Color {
+        r_in.spectrum(self.emit)
+    }
+
+    fn is_light(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::spectrum::Wavelengths;
+    use crate::vec3::Point3;
+
+    #[test]
+    fn indices_of_refraction_fall_with_wavelength() {
+        // Schott N-BK7 with n_d = 1.5168
+        let bk7 = Ior::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        };
+        assert!((bk7.at(D_LINE) - 1.5168).abs() < 1e-4);
+        let cauchy = Ior::Cauchy {
+            a: 1.5046,
+            b: 0.0042,
+        };
+        for ior in [bk7, cauchy] {
+            assert!(ior.is_dispersive());
+            assert!(ior.at(450.0) > ior.at(550.0) && ior.at(550.0) > ior.at(650.0));
+        }
+        assert_eq!(Ior::Constant(1.33).at(400.0), 1.33);
+        assert!(!Ior::Constant(1.33).is_dispersive());
+    }
+
+    #[test]
+    fn thin_films_interfere() {
+        let film = |thickness: f64, ior: f64, base_ior: f64| ThinFilm {
+            base: Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            thickness,
+            ior,
+            base_ior,
+        };
+        // A soap film much thinner than the light's wavelength reflects nothing.
+        assert!(film(0.0, 1.33, 1.0).film_reflectance(1.0, 1.0, 1.0, 550.0) < 1e-12);
+
+        // Quarter wave magnesium fluoride on glass, the classic anti-reflection coating
+        let coating = film(550.0 / (4.0 * 1.38), 1.38, 1.5);
+        let expected = ((1.5 - 1.38f64.powi(2)) / (1.5 + 1.38f64.powi(2))).powi(2);
+        let r = coating.film_reflectance(1.0, 1.0, 1.5, 550.0);
+        assert!((r - expected).abs() < 1e-9, "{}", r);
+        assert!(r < Dielectric::reflectance(1.0, 1.0 / 1.5));
+        // Half wave layers are invisible
+        let r = film(550.0 / (2.0 * 1.38), 1.38, 1.5).film_reflectance(1.0, 1.0, 1.5, 550.0);
+        assert!((r - Dielectric::reflectance(1.0, 1.0 / 1.5)).abs() < 1e-9);
+
+        // The color changes with the viewing angle.
+        let soap = film(400.0, 1.33, 1.0);
+        let hue = |cosine: f64| {
+            let [r, g, b] =
+                RGB_WAVELENGTHS.map(|lambda| soap.film_reflectance(cosine, 1.0, 1.0, lambda));
+            (r - b, g - b)
+        };
+        assert_ne!(hue(1.0), hue(0.5));
+    }
+
+    #[test]
+    fn abbe_number_sets_the_dispersion() {
+        let flint = Ior::from_abbe(1.62, 36.4);
+        assert!((flint.at(D_LINE) - 1.62).abs() < 1e-12);
+        let v_d = (flint.at(D_LINE) - 1.0) / (flint.at(F_LINE) - flint.at(C_LINE));
+        assert!((v_d - 36.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn spectral_paths_refract_at_their_hero_wavelength() {
+        let glass = Dielectric {
+            ior: Ior::from_abbe(1.6, 20.0),
+        };
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let refracted = |wavelengths: Option<Wavelengths>| {
+            let r = Ray {
+                origin: Point3::new(-1.0, 1.0, 0.0),
+                direction: Vec3::new(1.0, -1.0, 0.0),
+                wavelengths,
+                polarized: false,
+            };
+            let rec = HitRecord::new(
+                Point3::new(0.0, 0.0, 0.0),
+                1.0,
+                &r,
+                &normal,
+                (0.0, 0.0),
+                &glass,
+            );
+            // Always refract rather than reflect
+            let mut sampler = IndependentSampler::new(0, (0, 0), 0);
+            loop {
+                match glass.scatter(&r, &rec, &mut sampler) {
+                    Some(Scatter::Specular {
+                        attenuation,
+                        scattered,
+                    }) if scattered.direction.y < 0.0 => break (attenuation, scattered),
+                    _ => {}
+                }
+            }
+        };
+
+        let (attenuation, rgb) = refracted(None);
+        assert_eq!(attenuation, Color::new(1.0, 1.0, 1.0));
+        let (attenuation, blue) = refracted(Some(Wavelengths::sample(0.15)));
+        assert_eq!(attenuation, Color::new(3.0, 0.0, 0.0));
+        let (_, red) = refracted(Some(Wavelengths::sample(0.7)));
+        // Blue bends more towards the normal than the d line, and red less.
+        let bend = |r: Ray| r.direction.x / r.direction.length();
+        assert!(bend(blue) < bend(rgb) && bend(rgb) < bend(red));
+        assert_eq!(
+            red.wavelengths.map(|mut w| w.hero_only()),
+            Some(Color::new(1.0, 0.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn glass_polarizes_at_brewster_angle() {
+        let glass = Dielectric::new(1.5);
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let (sin, cos) = 1.5_f64.atan().sin_cos();
+        let r = Ray::new(Point3::new(-sin, cos, 0.0), Vec3::new(sin, -cos, 0.0));
+        let rec = HitRecord::new(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            &r,
+            &normal,
+            (0.0, 0.0),
+            &glass,
+        );
+        let reflected = glass
+            .polarization(&r, &rec, Vec3::new(sin, cos, 0.0))
+            .unwrap();
+        // Unpolarized light comes off entirely polarized across the plane of incidence
+        assert!((reflected.mueller.m[1][0].g - 1.0).abs() < 1e-9);
+        assert!(reflected.frame.z.abs() > 0.999);
+        let refracted = glass
+            .polarization(&r, &rec, Vec3::new(0.3, -1.0, 0.0))
+            .unwrap();
+        assert!(refracted.mueller.m[1][0].g < 0.0);
+    }
+
+    #[test]
+    fn polarized_paths_reflect_off_glass_per_fresnel() {
+        let glass = Dielectric::new(1.5);
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let (sin, cos) = 1.5_f64.atan().sin_cos();
+        let mut r = Ray::new(Point3::new(-sin, cos, 0.0), Vec3::new(sin, -cos, 0.0));
+        r.polarized = true;
+        let rec = HitRecord::new(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            &r,
+            &normal,
+            (0.0, 0.0),
+            &glass,
+        );
+        let n = 40_000;
+        let reflected = (0..n)
+            .filter(|&sample| {
+                let mut sampler = IndependentSampler::new(0, (0, 0), sample);
+                match glass.scatter(&r, &rec, &mut sampler) {
+                    Some(Scatter::Specular { scattered, .. }) => scattered.direction.y > 0.0,
+                    _ => false,
+                }
+            })
+            .count() as f64
+            / n as f64;
+        // About 0.074 at Brewster's angle, where Schlick's approximation gives 0.057
+        let exact = unpolarized_reflectance(cos, 1.5.into());
+        assert!((reflected - exact).abs() < 0.005, "{} {}", reflected, exact);
+    }
+
+    #[test]
+    fn metals_reflect_their_albedo_head_on() {
+        for reflectance in [0.05, 0.5, 0.9] {
+            let eta = conductor_ior(reflectance);
+            let (r_s, r_p) = fresnel_reflection(1.0, eta);
+            assert!((r_s.norm_sqr() - reflectance).abs() < 1e-9);
+            assert!((r_p.norm_sqr() - reflectance).abs() < 1e-9);
+        }
+    }
 }
@@ -0,0 +1,295 @@
+//! Rough surfaces made of mirror-like microfacets whose normals follow the GGX (Trowbridge-Reitz)
+//! distribution, with Smith masking and shadowing, after Walter et al. 2007. Directions are
+//! sampled from the distribution of normals visible from the incident direction, Heitz 2018, so
+//! that every sample is weighted by the masking of the outgoing direction alone.
+
+use crate::onb::Onb;
+use crate::pdf::Pdf;
+use crate::polarization::unpolarized_reflectance;
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+
+// Widths below which the distribution is treated as a perfect mirror.
+const SMOOTH_ALPHA: f64 = 1e-3;
+
+/// GGX distribution of microfacet normals in a frame with the surface normal along z.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ggx {
+    pub alpha: f64,
+}
+
+impl Ggx {
+    /// Distribution for a perceptual `roughness` between 0 for a mirror and 1 for a very rough
+    /// surface, whose square is the width `alpha`.
+    pub fn from_roughness(roughness: f64) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Self {
+            alpha: roughness * roughness,
+        }
+    }
+
+    /// Whether the surface is smooth enough to scatter like a perfect mirror or window.
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < SMOOTH_ALPHA
+    }
+
+    /// Density of facet normals `h` per unit area of the surface.
+    pub fn d(&self, h: Vec3) -> f64 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = h.z * h.z * (a2 - 1.0) + 1.0;
+        a2 / (PI * t * t)
+    }
+
+    // Smith's auxiliary function, the facet area hidden from `w` over the area seen from it.
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    /// Fraction of the facets seen from `w` that are not masked by others.
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of the facets that are seen from both `wo` and `wi`, taking the correlation of
+    /// their heights into account.
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Facet normal visible from `wo`, above the surface, for uniform numbers `(u1, u2)`.
+    pub fn sample_visible(&self, wo: Vec3, (u1, u2): (f64, f64)) -> Vec3 {
+        // Stretch to the hemisphere configuration, sample the projected disk there, and unstretch.
+        let v = Vec3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z).unit_vector();
+        let len2 = v.x * v.x + v.y * v.y;
+        let t1 = if len2 > 0.0 {
+            Vec3::new(-v.y, v.x, 0.0) / len2.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = v.cross(t1);
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = (1.0 + v.z) / 2.0;
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let p3 = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        let n = p1 * t1 + p2 * t2 + p3 * v;
+        Vec3::new(self.alpha * n.x, self.alpha * n.y, n.z.max(0.0)).unit_vector()
+    }
+
+    /// Density with which [`Ggx::sample_visible`] picks `h` from `wo`.
+    pub fn visible_pdf(&self, wo: Vec3, h: Vec3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(&h).max(0.0) * self.d(h) / wo.z
+    }
+}
+
+/// Light scattered by a single facet of a [`RoughSurface`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Facet {
+    /// Cosine between the incident direction and the facet normal, for its Fresnel factor.
+    pub cosine: f64,
+    pub reflected: bool,
+    /// BSDF times the cosine to the surface normal, without the Fresnel factor.
+    pub bsdf_cos: f64,
+    /// Density of the direction among the samples of the facet, before choosing between
+    /// reflection and refraction.
+    pub pdf: f64,
+}
+
+/// Rough conductor, or rough boundary of a dielectric with relative index of refraction `eta`
+/// (that past the surface over that before it), seen along one incident direction. Samples
+/// directions as a [`Pdf`] and gives the terms of the BSDF for each.
+pub struct RoughSurface {
+    frame: Onb,
+    // Towards where the light goes, in the frame.
+    wo: Vec3,
+    ggx: Ggx,
+    eta: Option<f64>,
+}
+
+impl RoughSurface {
+    /// `normal` faces the side `incident` comes from.
+    pub fn new(normal: Vec3, incident: Vec3, ggx: Ggx, eta: Option<f64>) -> Self {
+        let frame = Onb::from_w(normal);
+        let wo = frame.to_local(-incident.unit_vector());
+        Self {
+            frame,
+            wo,
+            ggx,
+            eta,
+        }
+    }
+
+    /// How the facet that scatters into `direction` does so, `None` if none can.
+    pub fn facet(&self, direction: Vec3) -> Option<Facet> {
+        let (wo, ggx) = (self.wo, self.ggx);
+        let wi = self.frame.to_local(direction.unit_vector());
+        // Nothing leaves along the surface, which is where lost samples go.
+        if wo.z <= 0.0 || wi.z.abs() < 1e-9 {
+            return None;
+        }
+        if wi.z > 0.0 {
+            let h = (wo + wi).unit_vector();
+            let cosine = wo.dot(&h);
+            if cosine <= 0.0 {
+                return None;
+            }
+            return Some(Facet {
+                cosine,
+                reflected: true,
+                bsdf_cos: ggx.d(h) * ggx.g(wo, wi) / (4.0 * wo.z),
+                pdf: ggx.visible_pdf(wo, h) / (4.0 * cosine),
+            });
+        }
+
+        let eta = self.eta?;
+        let h = wo + eta * wi;
+        if h.near_zero() {
+            return None;
+        }
+        let h = if h.z < 0.0 { -h } else { h }.unit_vector();
+        let (cos_o, cos_i) = (wo.dot(&h), wi.dot(&h));
+        if cos_o <= 0.0 || cos_i >= 0.0 {
+            return None;
+        }
+        // Change of variables from the facet normal to the refracted direction.
+        let denominator = cos_o + eta * cos_i;
+        let jacobian = eta * eta * -cos_i / (denominator * denominator);
+        Some(Facet {
+            cosine: cos_o,
+            reflected: false,
+            bsdf_cos: ggx.d(h) * ggx.g(wo, wi) * cos_o * jacobian / wo.z,
+            pdf: ggx.visible_pdf(wo, h) * jacobian,
+        })
+    }
+
+    /// Fraction of the light a dielectric facet reflects at `cosine`, 1 for conductors, whose
+    /// Fresnel factor is left to the caller.
+    pub fn reflectance(&self, cosine: f64) -> f64 {
+        match self.eta {
+            Some(eta) => unpolarized_reflectance(cosine, eta.into()),
+            None => 1.0,
+        }
+    }
+
+    /// The part of `facet` that is reflected or refracted, whichever it is.
+    pub fn fraction(&self, facet: &Facet) -> f64 {
+        let reflectance = self.reflectance(facet.cosine);
+        if facet.reflected {
+            reflectance
+        } else {
+            1.0 - reflectance
+        }
+    }
+}
+
+impl Pdf for RoughSurface {
+    fn value(&self, direction: Vec3) -> f64 {
+        match self.facet(direction) {
+            Some(facet) => facet.pdf * self.fraction(&facet),
+            None => 0.0,
+        }
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let wo = self.wo;
+        let h = self.ggx.sample_visible(wo, sampler.get_2d());
+        let cosine = wo.dot(&h);
+        let (direction, reflected) = match self.eta {
+            Some(eta) if sampler.get_1d() >= self.reflectance(cosine) => {
+                let cos_t = (1.0 - (1.0 - cosine * cosine) / (eta * eta))
+                    .max(0.0)
+                    .sqrt();
+                (-wo / eta + (cosine / eta - cos_t) * h, false)
+            }
+            _ => (2.0 * cosine * h - wo, true),
+        };
+        // Light a steep facet sends to the wrong side of the surface is lost. It leaves along the
+        // surface instead, where the density is zero, rather than pass for the other event.
+        if (direction.z > 0.0) != reflected {
+            return self.frame.u;
+        }
+        self.frame.local(direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diffusion::random_unit_vector;
+    use crate::sampler::IndependentSampler;
+
+    #[test]
+    fn projected_facet_area_is_one() {
+        let ggx = Ggx::from_roughness(0.5);
+        let n = 2000;
+        let mut sum = 0.0;
+        for i in 0..n {
+            // Midpoint rule over cos θ, the density being rotationally symmetric
+            let cos = (i as f64 + 0.5) / n as f64;
+            let h = Vec3::new((1.0 - cos * cos).sqrt(), 0.0, cos);
+            sum += ggx.d(h) * cos * 2.0 * PI / n as f64;
+        }
+        assert!((sum - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn sampling_matches_the_density() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let incident = Vec3::new(0.6, -0.8, 0.0);
+        for eta in [None, Some(1.5), Some(1.0 / 1.5)] {
+            let surface = RoughSurface::new(normal, incident, Ggx::from_roughness(0.6), eta);
+            let n = 200_000;
+            let mut total_pdf = 0.0;
+            let mut valid = 0.0;
+            let mut uniform_albedo = 0.0;
+            let mut sampled_albedo = 0.0;
+            for i in 0..n {
+                let mut sampler = IndependentSampler::new(1, (0, 0), i);
+                // The density integrates to the fraction of samples that leave the surface, since
+                // some reflect off facets back into it
+                let direction = random_unit_vector(&mut sampler);
+                total_pdf += surface.value(direction) * 4.0 * PI / n as f64;
+                if let Some(facet) = surface.facet(direction) {
+                    uniform_albedo +=
+                        facet.bsdf_cos * surface.fraction(&facet) * 4.0 * PI / n as f64;
+                }
+                // And sampling it estimates the same albedo
+                let direction = surface.generate(&mut sampler);
+                if let Some(facet) = surface.facet(direction) {
+                    valid += 1.0 / n as f64;
+                    sampled_albedo += facet.bsdf_cos * surface.fraction(&facet)
+                        / surface.value(direction)
+                        / n as f64;
+                }
+            }
+            assert!(
+                (total_pdf - valid).abs() < 0.02 && valid > 0.8,
+                "{:?}: {} {}",
+                eta,
+                total_pdf,
+                valid
+            );
+            assert!(
+                (uniform_albedo - sampled_albedo).abs() < 0.02,
+                "{:?}: {} {}",
+                eta,
+                uniform_albedo,
+                sampled_albedo
+            );
+            assert!(sampled_albedo <= 1.0);
+        }
+    }
+}
@@ -0,0 +1,571 @@
+use crate::color::Color;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::triangle::TriangleMesh;
+use crate::vec3::{Point3, Vec3};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::SplitWhitespace;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct ObjError {
+    pub path: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.path.display(), self.message)
+        } else {
+            write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// Loads a Wavefront OBJ file as one mesh per group and material.
+///
+/// Faces without a `usemtl` material get `default_material`. Material libraries are looked up
+/// relative to the OBJ file.
+pub fn load(
+    path: &Path,
+    default_material: Arc<dyn Material>,
+) -> Result<Vec<TriangleMesh>, ObjError> {
+    let source = fs::read_to_string(path).map_err(|e| ObjError {
+        path: path.to_path_buf(),
+        line: 0,
+        message: e.to_string(),
+    })?;
+    parse(&source, path, default_material)
+}
+
+pub fn parse(
+    source: &str,
+    path: &Path,
+    default_material: Arc<dyn Material>,
+) -> Result<Vec<TriangleMesh>, ObjError> {
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut normals = Vec::new();
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+
+    // Faces are collected per group and material, in order of first use.
+    let mut groups: Vec<Group> = Vec::new();
+    let mut group_name = String::new();
+    let mut material = (String::new(), default_material);
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: String| ObjError {
+            path: path.to_path_buf(),
+            line: line_number,
+            message,
+        };
+
+        let line = line.split('#').next().unwrap_or("");
+        let mut fields = line.split_whitespace();
+        let keyword = match fields.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        match keyword {
+            "v" => positions.push(parse_vec3(&mut fields).map_err(error)?),
+            "vn" => normals.push(parse_vec3(&mut fields).map_err(error)?),
+            "vt" => {
+                let u = parse_float(fields.next(), "u").map_err(error)?;
+                let v = fields.next().map_or(Ok(0.0), |v| parse_float(Some(v), "v"));
+                uvs.push((u, v.map_err(error)?));
+            }
+            "f" => {
+                let corners = fields
+                    .map(|corner| parse_corner(corner, positions.len(), uvs.len(), normals.len()))
+                    .collect::<Result<Vec<Corner>, String>>()
+                    .map_err(error)?;
+                if corners.len() < 3 {
+                    return Err(error(format!(
+                        "face needs at least 3 vertices, got {}",
+                        corners.len()
+                    )));
+                }
+                let group = match groups
+                    .iter_mut()
+                    .position(|g| g.name == group_name && g.material_name == material.0)
+                {
+                    Some(i) => &mut groups[i],
+                    None => {
+                        groups.push(Group::new(&group_name, &material.0, &material.1));
+                        groups.last_mut().unwrap()
+                    }
+                };
+                let corner_positions: Vec<Point3> =
+                    corners.iter().map(|c| positions[c.position]).collect();
+                for [a, b, c] in triangulate(&corner_positions) {
+                    group.faces.push([corners[a], corners[b], corners[c]]);
+                }
+            }
+            "g" | "o" => group_name = fields.collect::<Vec<_>>().join(" "),
+            "usemtl" => {
+                let name = fields.collect::<Vec<_>>().join(" ");
+                let found = materials
+                    .get(&name)
+                    .ok_or_else(|| error(format!("unknown material `{}`", name)))?;
+                material = (name, Arc::clone(found));
+            }
+            "mtllib" => {
+                for library in fields {
+                    let library_path = path.with_file_name(library);
+                    let source = fs::read_to_string(&library_path)
+                        .map_err(|e| error(format!("{}: {}", library_path.display(), e)))?;
+                    for (name, mtl) in parse_mtl(&source, &library_path)? {
+                        materials.insert(name, mtl.to_material());
+                    }
+                }
+            }
+            // Smoothing groups, lines, points and free-form geometry are not supported.
+            _ => {}
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|group| group.into_mesh(&positions, &uvs, &normals))
+        .collect::<Result<Vec<_>, String>>()
+        .map_err(|message| ObjError {
+            path: path.to_path_buf(),
+            line: 0,
+            message,
+        })
+}
+
+// Indices of one face corner into the file wide position, texture and normal arrays.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+struct Group {
+    name: String,
+    material_name: String,
+    material: Arc<dyn Material>,
+    faces: Vec<[Corner; 3]>,
+}
+
+impl Group {
+    fn new(name: &str, material_name: &str, material: &Arc<dyn Material>) -> Self {
+        Self {
+            name: name.to_string(),
+            material_name: material_name.to_string(),
+            material: Arc::clone(material),
+            faces: Vec::new(),
+        }
+    }
+
+    // Gives every distinct corner its own mesh vertex so that the attribute buffers can share
+    // one index.
+    fn into_mesh(
+        self,
+        positions: &[Point3],
+        uvs: &[(f64, f64)],
+        normals: &[Vec3],
+    ) -> Result<TriangleMesh, String> {
+        let mut vertices: Vec<Corner> = Vec::new();
+        let mut vertex_of: HashMap<Corner, usize> = HashMap::new();
+        let indices = self
+            .faces
+            .iter()
+            .map(|face| {
+                face.map(|corner| {
+                    *vertex_of.entry(corner).or_insert_with(|| {
+                        vertices.push(corner);
+                        vertices.len() - 1
+                    })
+                })
+            })
+            .collect();
+
+        // Attributes only some corners have are dropped for the whole mesh.
+        let mesh_normals = if vertices.iter().all(|v| v.normal.is_some()) {
+            vertices
+                .iter()
+                .map(|v| normals[v.normal.unwrap()])
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let mesh_uvs = if vertices.iter().all(|v| v.uv.is_some()) {
+            vertices.iter().map(|v| uvs[v.uv.unwrap()]).collect()
+        } else {
+            Vec::new()
+        };
+        TriangleMesh::new(
+            vertices.iter().map(|v| positions[v.position]).collect(),
+            mesh_normals,
+            mesh_uvs,
+            indices,
+            self.material,
+        )
+    }
+}
+
+fn parse_float(field: Option<&str>, name: &str) -> Result<f64, String> {
+    let field = field.ok_or_else(|| format!("missing {} coordinate", name))?;
+    field
+        .parse()
+        .map_err(|_| format!("`{}` is not a number", field))
+}
+
+fn parse_vec3(fields: &mut SplitWhitespace) -> Result<Vec3, String> {
+    Ok(Vec3::new(
+        parse_float(fields.next(), "x")?,
+        parse_float(fields.next(), "y")?,
+        parse_float(fields.next(), "z")?,
+    ))
+}
+
+// `v`, `v/vt`, `v//vn` or `v/vt/vn`, 1-based or negative relative to the end.
+fn parse_corner(
+    corner: &str,
+    positions: usize,
+    uvs: usize,
+    normals: usize,
+) -> Result<Corner, String> {
+    let mut parts = corner.split('/');
+    let index = |part: Option<&str>, count: usize, what: &str| -> Result<Option<usize>, String> {
+        let part = match part {
+            Some(part) if !part.is_empty() => part,
+            _ => return Ok(None),
+        };
+        let index: i64 = part
+            .parse()
+            .map_err(|_| format!("invalid {} index `{}`", what, part))?;
+        let resolved = if index < 0 {
+            count as i64 + index
+        } else {
+            index - 1
+        };
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            Err(format!(
+                "{} index {} is out of range, {} defined so far",
+                what, index, count
+            ))
+        } else {
+            Ok(Some(resolved as usize))
+        }
+    };
+
+    let position = index(parts.next(), positions, "vertex")?
+        .ok_or_else(|| format!("face corner `{}` has no vertex index", corner))?;
+    let uv = index(parts.next(), uvs, "texture coordinate")?;
+    let normal = index(parts.next(), normals, "normal")?;
+    Ok(Corner {
+        position,
+        uv,
+        normal,
+    })
+}
+
+// Splits a planar polygon into triangles by ear clipping, falling back to a fan for polygons
+// that are too degenerate to clip.
+fn triangulate(polygon: &[Point3]) -> Vec<[usize; 3]> {
+    let n = polygon.len();
+    let fan = || (1..n - 1).map(|i| [0, i, i + 1]).collect();
+    if n == 3 {
+        return fan();
+    }
+
+    // Newell's method gives a robust normal for the projection.
+    let normal = (0..n).fold(Vec3::new(0.0, 0.0, 0.0), |acc, i| {
+        let (a, b) = (polygon[i], polygon[(i + 1) % n]);
+        acc + Vec3::new(
+            (a.y - b.y) * (a.z + b.z),
+            (a.z - b.z) * (a.x + b.x),
+            (a.x - b.x) * (a.y + b.y),
+        )
+    });
+    if normal.near_zero() {
+        return fan();
+    }
+    let convex = |a: Point3, b: Point3, c: Point3| (b - a).cross(c - b).dot(&normal) > 0.0;
+    let inside = |p: Point3, a: Point3, b: Point3, c: Point3| {
+        (b - a).cross(p - a).dot(&normal) >= 0.0
+            && (c - b).cross(p - b).dot(&normal) >= 0.0
+            && (a - c).cross(p - c).dot(&normal) >= 0.0
+    };
+
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut triangles = Vec::with_capacity(n - 2);
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let ear = (0..m).find(|&i| {
+            let [a, b, c] = [
+                remaining[(i + m - 1) % m],
+                remaining[i],
+                remaining[(i + 1) % m],
+            ];
+            let (pa, pb, pc) = (polygon[a], polygon[b], polygon[c]);
+            convex(pa, pb, pc)
+                && remaining
+                    .iter()
+                    .filter(|&&j| j != a && j != b && j != c)
+                    .all(|&j| !inside(polygon[j], pa, pb, pc))
+        });
+        match ear {
+            Some(i) => {
+                triangles.push([
+                    remaining[(i + m - 1) % m],
+                    remaining[i],
+                    remaining[(i + 1) % m],
+                ]);
+                remaining.remove(i);
+            }
+            None => return fan(),
+        }
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+/// Material statements of an MTL file that wave-tracer understands.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MtlMaterial {
+    pub diffuse: Color,
+    pub specular: Color,
+    pub emission: Color,
+    pub shininess: f64,
+    pub ior: f64,
+    pub dissolve: f64,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::new(0.0, 0.0, 0.0),
+            emission: Color::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            ior: 1.5,
+            dissolve: 1.0,
+        }
+    }
+}
+
+fn max_component(c: Color) -> f64 {
+    c.r.max(c.g).max(c.b)
+}
+
+impl MtlMaterial {
+    /// Closest wave-tracer material: see-through materials become glass, materials whose
+    /// specular color outweighs the diffuse one become metal and everything else is diffuse.
+    pub fn to_material(&self) -> Arc<dyn Material> {
+        if max_component(self.emission) > 0.0 {
+            Arc::new(DiffuseLight {
+                emit: self.emission,
+            })
+        } else if self.dissolve < 1.0 {
+            Arc::new(Dielectric::new(self.ior))
+        } else if max_component(self.specular) > max_component(self.diffuse) {
+            // Map the Phong exponent to a roughness, Walter et al. 2007
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt();
+            Arc::new(Metal::new(self.specular, fuzz))
+        } else {
+            Arc::new(Lambertian::new(self.diffuse))
+        }
+    }
+}
+
+pub fn parse_mtl(source: &str, path: &Path) -> Result<Vec<(String, MtlMaterial)>, ObjError> {
+    let mut materials: Vec<(String, MtlMaterial)> = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let error = |message: String| ObjError {
+            path: path.to_path_buf(),
+            line: index + 1,
+            message,
+        };
+        let line = line.split('#').next().unwrap_or("");
+        let mut fields = line.split_whitespace();
+        let keyword = match fields.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        if keyword == "newmtl" {
+            let name = fields.collect::<Vec<_>>().join(" ");
+            materials.push((name, MtlMaterial::default()));
+            continue;
+        }
+
+        let current = match materials.last_mut() {
+            Some((_, material)) => material,
+            None => return Err(error(format!("`{}` before any `newmtl`", keyword))),
+        };
+        let color = |fields: &mut SplitWhitespace| -> Result<Color, String> {
+            let r = parse_float(fields.next(), "red")?;
+            // A single value sets all three channels.
+            match fields.next() {
+                None => Ok(Color::new(r, r, r)),
+                g => Ok(Color::new(
+                    r,
+                    parse_float(g, "green")?,
+                    parse_float(fields.next(), "blue")?,
+                )),
+            }
+        };
+        match keyword {
+            "Kd" => current.diffuse = color(&mut fields).map_err(error)?,
+            "Ks" => current.specular = color(&mut fields).map_err(error)?,
+            "Ke" => current.emission = color(&mut fields).map_err(error)?,
+            "Ns" => current.shininess = parse_float(fields.next(), "Ns").map_err(error)?,
+            "Ni" => current.ior = parse_float(fields.next(), "Ni").map_err(error)?,
+            "d" => current.dissolve = parse_float(fields.next(), "d").map_err(error)?,
+            "Tr" => current.dissolve = 1.0 - parse_float(fields.next(), "Tr").map_err(error)?,
+            // Ambient color, illumination models and texture maps are not supported.
+            _ => {}
+        }
+    }
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn parse_str(source: &str) -> Result<Vec<TriangleMesh>, ObjError> {
+        parse(source, Path::new("model.obj"), material())
+    }
+
+    fn parse_error(source: &str) -> ObjError {
+        match parse_str(source) {
+            Err(error) => error,
+            Ok(_) => panic!("`{}` should not parse", source),
+        }
+    }
+
+    #[test]
+    fn parses_faces_with_attributes() {
+        let meshes = parse_str(
+            "# quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1 4/4/1
+",
+        )
+        .unwrap();
+        assert_eq!(meshes.len(), 1);
+        let mesh = &meshes[0];
+        assert_eq!(mesh.indices.len(), 2);
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.normals, vec![Vec3::new(0.0, 0.0, 1.0); 4]);
+        let corner = mesh
+            .positions
+            .iter()
+            .position(|&p| p == Point3::new(1.0, 1.0, 0.0))
+            .unwrap();
+        assert_eq!(mesh.uvs[corner], (1.0, 1.0));
+    }
+
+    #[test]
+    fn negative_indices_and_groups() {
+        let meshes = parse_str(
+            "g first
+v 0 0 0
+v 1 0 0
+v 0 1 0
+f -3 -2 -1
+g second
+v 0 0 1
+v 1 0 1
+v 0 1 1
+f -3//  -2 -1
+g first
+f 1 2 3
+",
+        )
+        .unwrap();
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0].indices.len(), 2);
+        assert_eq!(meshes[1].positions[0], Point3::new(0.0, 0.0, 1.0));
+        assert!(meshes[1].normals.is_empty());
+    }
+
+    #[test]
+    fn reports_line_numbers() {
+        let error = parse_error("v 0 0 0\nv 1 0 0\n\nf 1 2 3\n");
+        assert_eq!(error.line, 4);
+        assert_eq!(
+            error.to_string(),
+            "model.obj:4: vertex index 3 is out of range, 2 defined so far"
+        );
+
+        let error = parse_error("v 0 zero 0\n");
+        assert_eq!(
+            (error.line, error.message.as_str()),
+            (1, "`zero` is not a number")
+        );
+
+        let error = parse_error("usemtl missing\n");
+        assert_eq!(error.message, "unknown material `missing`");
+    }
+
+    #[test]
+    fn triangulates_concave_polygon() {
+        // L shape, a fan from the first vertex would cover the notch
+        let polygon = [
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(2.0, 0.0, 0.0),
+            Point3::new(2.0, 1.0, 0.0),
+            Point3::new(1.0, 1.0, 0.0),
+            Point3::new(1.0, 2.0, 0.0),
+            Point3::new(0.0, 2.0, 0.0),
+        ];
+        let triangles = triangulate(&polygon);
+        assert_eq!(triangles.len(), 4);
+        let area: f64 = triangles
+            .iter()
+            .map(|&[a, b, c]| {
+                0.5 * (polygon[b] - polygon[a])
+                    .cross(polygon[c] - polygon[a])
+                    .length()
+            })
+            .sum();
+        assert!((area - 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn parses_mtl() {
+        let error = parse_mtl(
+            "newmtl glass\nNi 1.5\n\nnewmtl bad\nKs 1 x 1\n",
+            Path::new("model.mtl"),
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "model.mtl:5: `x` is not a number");
+
+        let materials = parse_mtl(
+            "newmtl glass\nNi 1.33\nd 0.2\nnewmtl steel\nKd 0.1\nKs 0.9\nNs 200\n",
+            Path::new("model.mtl"),
+        )
+        .unwrap();
+        assert_eq!(materials[0].0, "glass");
+        assert_eq!(materials[0].1.ior, 1.33);
+        assert_eq!(materials[0].1.dissolve, 0.2);
+        assert_eq!(materials[1].1.diffuse, Color::new(0.1, 0.1, 0.1));
+        assert_eq!(materials[1].1.specular, Color::new(0.9, 0.9, 0.9));
+        assert_eq!(materials[1].1.shininess, 200.0);
+    }
+}
@@ -0,0 +1,32 @@
+use crate::vec3::Vec3;
+
+/// Orthonormal basis with `w` along a given direction, used to place local samples in the world.
+#[derive(Debug, Copy, Clone)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn from_w(w: Vec3) -> Self {
+        let w = w.unit_vector();
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(a).unit_vector();
+        let u = w.cross(v);
+        Self { u, v, w }
+    }
+
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+
+    /// Coordinates of the world vector `a` in the basis, the inverse of [`Onb::local`].
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+}
@@ -0,0 +1,220 @@
+use crate::diffusion::random_cosine_direction;
+use crate::environment::{Environment, EnvironmentMap};
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::onb::Onb;
+use crate::sampler::Sampler;
+use crate::vec3::{Point3, Vec3};
+use std::f64::consts::PI;
+
+/// Distribution over directions, with densities measured in solid angle.
+pub trait Pdf {
+    fn value(&self, direction: Vec3) -> f64;
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3;
+}
+
+/// Cosine-weighted hemisphere around a normal.
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(normal: Vec3) -> Self {
+        Self {
+            uvw: Onb::from_w(normal),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let cosine = direction.unit_vector().dot(&self.uvw.w);
+        (cosine / PI).max(0.0)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        self.uvw.local(random_cosine_direction(sampler))
+    }
+}
+
+/// Directions from `origin` towards points on `objects`, typically the lights of a scene.
+pub struct HittablePdf<'a> {
+    objects: &'a dyn Hittable,
+    origin: Point3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(objects: &'a dyn Hittable, origin: Point3) -> Self {
+        Self { objects, origin }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        self.objects.pdf_value(self.origin, direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        self.objects.random(self.origin, sampler)
+    }
+}
+
+/// Directions from `origin` towards the lights of a scene and its environment map, each picked
+/// half of the time when there are both.
+pub struct LightPdf<'a> {
+    objects: Option<HittablePdf<'a>>,
+    environment: Option<&'a EnvironmentMap>,
+}
+
+impl<'a> LightPdf<'a> {
+    /// `None` when there is nothing to sample.
+    pub fn new(
+        lights: &'a HittableList,
+        environment: &'a Environment,
+        origin: Point3,
+    ) -> Option<Self> {
+        let objects = (!lights.objects.is_empty()).then(|| HittablePdf::new(lights, origin));
+        let environment = environment.sampled_map();
+        (objects.is_some() || environment.is_some()).then_some(Self {
+            objects,
+            environment,
+        })
+    }
+}
+
+impl Pdf for LightPdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        match (&self.objects, self.environment) {
+            (Some(objects), Some(map)) => {
+                0.5 * objects.value(direction) + 0.5 * map.value(direction)
+            }
+            (Some(objects), None) => objects.value(direction),
+            (None, Some(map)) => map.value(direction),
+            (None, None) => 0.0,
+        }
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        match (&self.objects, self.environment) {
+            (Some(objects), Some(map)) => {
+                if sampler.get_1d() < 0.5 {
+                    objects.generate(sampler)
+                } else {
+                    map.generate(sampler)
+                }
+            }
+            (Some(objects), None) => objects.generate(sampler),
+            (None, Some(map)) => map.generate(sampler),
+            (None, None) => unreachable!("LightPdf::new returns None without lights"),
+        }
+    }
+}
+
+/// Multiple importance sampling weight of a sample drawn with density `f_pdf` when `g_pdf` could
+/// also have produced it, Veach 1997.
+pub fn power_heuristic(f_pdf: f64, g_pdf: f64) -> f64 {
+    let f = f_pdf * f_pdf;
+    let g = g_pdf * g_pdf;
+    if f + g == 0.0 {
+        0.0
+    } else {
+        f / (f + g)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::diffusion::random_unit_vector;
+    use crate::image::Framebuffer;
+    use crate::material::{DiffuseLight, Material};
+    use crate::sampler::IndependentSampler;
+    use crate::sphere::Sphere;
+    use crate::triangle::TriangleMesh;
+    use std::sync::Arc;
+
+    // Monte Carlo estimate of the integral of the density over the sphere of directions.
+    fn total(pdf: &dyn Pdf) -> f64 {
+        let n = 200_000;
+        let mut sampler = IndependentSampler::new(0, (0, 0), 0);
+        let sum: f64 = (0..n)
+            .map(|_| pdf.value(random_unit_vector(&mut sampler)))
+            .sum();
+        4.0 * PI * sum / n as f64
+    }
+
+    // Generated directions must land where the density is nonzero.
+    fn covers(pdf: &dyn Pdf) -> bool {
+        let mut sampler = IndependentSampler::new(0, (0, 0), 1);
+        (0..1000).all(|_| pdf.value(pdf.generate(&mut sampler)) > 0.0)
+    }
+
+    fn lights() -> HittableList {
+        let material: Arc<dyn Material> = Arc::new(DiffuseLight {
+            emit: Color::new(1.0, 1.0, 1.0),
+        });
+        let quad = TriangleMesh::new(
+            vec![
+                Point3::new(-1.0, 2.0, -1.0),
+                Point3::new(1.0, 2.0, -1.0),
+                Point3::new(1.0, 2.0, 1.0),
+                Point3::new(-1.0, 2.0, 1.0),
+            ],
+            Vec::new(),
+            Vec::new(),
+            vec![[0, 1, 2], [0, 2, 3]],
+            Arc::clone(&material),
+        )
+        .unwrap();
+        let mut objects = Arc::new(quad).triangles();
+        objects.push(Box::new(Sphere {
+            center: Point3::new(3.0, 0.0, 0.0),
+            radius: 1.0,
+            material,
+        }));
+        HittableList { objects }
+    }
+
+    #[test]
+    fn cosine_pdf_is_normalized() {
+        let pdf = CosinePdf::new(Vec3::new(1.0, 2.0, 3.0));
+        assert!((total(&pdf) - 1.0).abs() < 0.02);
+        assert!(covers(&pdf));
+    }
+
+    #[test]
+    fn light_pdf_is_normalized() {
+        let lights = lights();
+        let pdf = HittablePdf::new(&lights, Point3::new(0.0, 0.0, 0.0));
+        assert!((total(&pdf) - 1.0).abs() < 0.02, "{}", total(&pdf));
+        assert!(covers(&pdf));
+    }
+
+    #[test]
+    fn light_and_environment_mixture_is_normalized() {
+        let lights = lights();
+        let sky = Framebuffer::from_pixels(
+            4,
+            2,
+            (0..8).map(|i| Color::new(i as f64, 1.0, 0.5)).collect(),
+        );
+        let environment = Environment::Map(Arc::new(EnvironmentMap::new(sky, 0.0, 1.0)));
+        let pdf = LightPdf::new(&lights, &environment, Point3::new(0.0, 0.0, 0.0)).unwrap();
+        assert!((total(&pdf) - 1.0).abs() < 0.02, "{}", total(&pdf));
+        assert!(covers(&pdf));
+        let empty = HittableList {
+            objects: Vec::new(),
+        };
+        assert!(
+            LightPdf::new(&empty, &Environment::default(), Point3::new(0.0, 0.0, 0.0)).is_none()
+        );
+    }
+
+    #[test]
+    fn power_heuristic_weights_sum_to_one() {
+        assert_eq!(power_heuristic(1.0, 0.0), 1.0);
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+        assert!((power_heuristic(2.0, 3.0) + power_heuristic(3.0, 2.0) - 1.0).abs() < 1e-12);
+    }
+}
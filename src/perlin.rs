@@ -0,0 +1,117 @@
+use crate::vec3::{Point3, Vec3};
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+const POINT_COUNT: usize = 256;
+
+/// Gradient noise over space, Perlin 1985 with random unit gradients.
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new<R: Rng>(rng: &mut R) -> Self {
+        let gradients = (0..POINT_COUNT)
+            .map(|_| {
+                Vec3::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .unit_vector()
+            })
+            .collect();
+        let mut permutation = || {
+            let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+            perm.shuffle(rng);
+            perm
+        };
+        Self {
+            gradients,
+            perm_x: permutation(),
+            perm_y: permutation(),
+            perm_z: permutation(),
+        }
+    }
+
+    /// Smooth noise in [-1, 1] varying over unit distances.
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let (i, j, k) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - i, p.y - j, p.z - k);
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+        let wrap = |n: i64| (n & (POINT_COUNT as i64 - 1)) as usize;
+
+        // Hermite smoothing hides the grid in the interpolation weights.
+        let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+        let (uu, vv, ww) = (smooth(u), smooth(v), smooth(w));
+
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradients[self.perm_x[wrap(i + di)]
+                        ^ self.perm_y[wrap(j + dj)]
+                        ^ self.perm_z[wrap(k + dk)]];
+                    let (fi, fj, fk) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * gradient.dot(&weight);
+                }
+            }
+        }
+        accum
+    }
+
+    /// Sum of `depth` octaves of absolute noise, each at twice the frequency and half the weight.
+    pub fn turbulence(&self, p: &Point3, depth: usize) -> f64 {
+        let mut accum = 0.0;
+        let mut p = *p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(&p);
+            weight *= 0.5;
+            p = 2.0 * p;
+        }
+        accum.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn noise_is_bounded_and_smooth() {
+        let perlin = Perlin::new(&mut StdRng::seed_from_u64(3));
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..10_000 {
+            let p = Point3::new(
+                rng.gen_range(-50.0..50.0),
+                rng.gen_range(-50.0..50.0),
+                rng.gen_range(-50.0..50.0),
+            );
+            let n = perlin.noise(&p);
+            assert!((-1.0..=1.0).contains(&n), "{}", n);
+            let nearby = perlin.noise(&(p + Vec3::new(1e-6, -1e-6, 1e-6)));
+            assert!((n - nearby).abs() < 1e-4);
+        }
+        // Zero at lattice points, where every gradient is dotted with a zero offset
+        assert_eq!(perlin.noise(&Point3::new(3.0, -7.0, 12.0)), 0.0);
+    }
+
+    #[test]
+    fn same_seed_same_noise() {
+        let a = Perlin::new(&mut StdRng::seed_from_u64(9));
+        let b = Perlin::new(&mut StdRng::seed_from_u64(9));
+        let p = Point3::new(0.3, 1.7, -2.2);
+        assert_eq!(a.noise(&p), b.noise(&p));
+        assert_eq!(a.turbulence(&p, 7), b.turbulence(&p, 7));
+    }
+}
@@ -0,0 +1,340 @@
+//! Polarized light transport. Polarized paths carry a Stokes vector per color channel instead of
+//! a radiance, and scattering events that polarize light apply a Mueller matrix to it. Both are
+//! expressed against a reference axis perpendicular to the direction the light travels, so
+//! Stokes vectors are rotated to a shared axis before they are combined or transformed.
+//!
+//! The first component of a Stokes vector is the radiance, the second the excess of light
+//! polarized along the reference axis over light polarized across it, the third the same for
+//! axes turned 45 degrees towards `direction × axis`, and the fourth the circular part.
+
+use crate::color::Color;
+use crate::onb::Onb;
+use crate::vec3::Vec3;
+use std::ops::{Add, Div, Mul, Sub};
+
+/// Complex number, for the Fresnel amplitudes of conductors and of total internal reflection.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    pub fn conj(self) -> Self {
+        Self::new(self.re, -self.im)
+    }
+
+    pub fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// Principal square root, with a non-negative real part.
+    pub fn sqrt(self) -> Self {
+        let r = self.norm_sqr().sqrt();
+        let re = ((r + self.re) / 2.0).max(0.0).sqrt();
+        let im = ((r - self.re) / 2.0).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+}
+
+impl From<f64> for Complex {
+    fn from(re: f64) -> Self {
+        Self::new(re, 0.0)
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        let n = rhs.norm_sqr();
+        let p = self * rhs.conj();
+        Self::new(p.re / n, p.im / n)
+    }
+}
+
+/// Fresnel amplitudes `(r_s, r_p)` of light reflected at `cos_i` to the normal off a surface
+/// with relative index of refraction `eta`, the index of the far side over that of the near
+/// side. Conductors have a complex `eta`, and so do dielectrics past the critical angle.
+pub fn fresnel_reflection(cos_i: f64, eta: Complex) -> (Complex, Complex) {
+    let cos_i = Complex::from(cos_i.clamp(0.0, 1.0));
+    let sin2_t = (Complex::from(1.0) - cos_i * cos_i) / (eta * eta);
+    let cos_t = (Complex::from(1.0) - sin2_t).sqrt();
+    let r_s = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let r_p = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    (r_s, r_p)
+}
+
+/// Fresnel amplitudes `(t_s, t_p)` of light refracted at `cos_i` to the normal into a
+/// dielectric with relative index of refraction `eta`, below the critical angle.
+pub fn fresnel_transmission(cos_i: f64, eta: f64) -> (f64, f64) {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    let cos_t = (1.0 - sin2_t).max(0.0).sqrt();
+    (
+        2.0 * cos_i / (cos_i + eta * cos_t),
+        2.0 * cos_i / (eta * cos_i + cos_t),
+    )
+}
+
+/// Fraction of unpolarized light reflected, the mean of the s and p reflectances.
+pub fn unpolarized_reflectance(cos_i: f64, eta: Complex) -> f64 {
+    let (r_s, r_p) = fresnel_reflection(cos_i, eta);
+    (r_s.norm_sqr() + r_p.norm_sqr()) / 2.0
+}
+
+/// Polarization state of the light at each color channel.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Stokes {
+    pub s: [Color; 4],
+    // Reference axis, `None` while the light is unpolarized.
+    frame: Option<Vec3>,
+}
+
+impl Stokes {
+    pub fn unpolarized(radiance: Color) -> Self {
+        let black = Color::new(0.0, 0.0, 0.0);
+        Self {
+            s: [radiance, black, black, black],
+            frame: None,
+        }
+    }
+
+    pub fn radiance(&self) -> Color {
+        self.s[0]
+    }
+
+    /// The same light, travelling along `direction`, against the reference axis `frame`
+    /// projected perpendicular to `direction`.
+    pub fn in_frame(&self, direction: Vec3, frame: Vec3) -> Self {
+        let direction = direction.unit_vector();
+        let mut axis = frame - frame.dot(&direction) * direction;
+        if axis.near_zero() {
+            axis = Onb::from_w(direction).u;
+        }
+        let axis = axis.unit_vector();
+        let mut s = self.s;
+        if let Some(old) = self.frame {
+            let phi = axis.dot(&direction.cross(old)).atan2(axis.dot(&old));
+            let (sin, cos) = (2.0 * phi).sin_cos();
+            s[1] = cos * self.s[1] + sin * self.s[2];
+            s[2] = -sin * self.s[1] + cos * self.s[2];
+        }
+        Self {
+            s,
+            frame: Some(axis),
+        }
+    }
+
+    /// Sum with `other`, both travelling along `direction`.
+    pub fn plus(&self, other: &Stokes, direction: Vec3) -> Self {
+        let other = match (self.frame, other.frame) {
+            (Some(frame), Some(_)) => other.in_frame(direction, frame),
+            _ => *other,
+        };
+        Self {
+            s: [0, 1, 2, 3].map(|i| self.s[i] + other.s[i]),
+            frame: self.frame.or(other.frame),
+        }
+    }
+}
+
+impl Mul<Color> for Stokes {
+    type Output = Stokes;
+
+    fn mul(self, rhs: Color) -> Stokes {
+        Stokes {
+            s: self.s.map(|s| s * rhs),
+            frame: self.frame,
+        }
+    }
+}
+
+/// Linear map of Stokes vectors at each color channel.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Mueller {
+    pub m: [[Color; 4]; 4],
+}
+
+impl Mueller {
+    /// The same matrix at every channel.
+    pub fn uniform(m: [[f64; 4]; 4]) -> Self {
+        Self {
+            m: m.map(|row| row.map(|v| Color::new(v, v, v))),
+        }
+    }
+
+    /// A matrix for each of the red, green and blue channels.
+    pub fn per_channel(channels: [[[f64; 4]; 4]; 3]) -> Self {
+        let m = [0, 1, 2, 3].map(|i| {
+            [0, 1, 2, 3]
+                .map(|j| Color::new(channels[0][i][j], channels[1][i][j], channels[2][i][j]))
+        });
+        Self { m }
+    }
+
+    /// Reflection with Fresnel amplitudes `r_s` and `r_p`, against the s axis perpendicular to
+    /// the plane of incidence, scaled to pass unpolarized light unchanged.
+    pub fn reflection(r_s: Complex, r_p: Complex) -> [[f64; 4]; 4] {
+        let cross = r_s * r_p.conj();
+        Self::normalized(r_s.norm_sqr(), r_p.norm_sqr(), cross.re, cross.im)
+    }
+
+    /// Refraction with Fresnel amplitudes `t_s` and `t_p`, like [`Mueller::reflection`].
+    pub fn transmission(t_s: f64, t_p: f64) -> [[f64; 4]; 4] {
+        Self::normalized(t_s * t_s, t_p * t_p, t_s * t_p, 0.0)
+    }
+
+    fn normalized(s: f64, p: f64, c: f64, d: f64) -> [[f64; 4]; 4] {
+        let a = (s + p) / 2.0;
+        if a <= 0.0 {
+            return Self::IDENTITY;
+        }
+        let b = (s - p) / 2.0 / a;
+        let (c, d) = (c / a, d / a);
+        [
+            [1.0, b, 0.0, 0.0],
+            [b, 1.0, 0.0, 0.0],
+            [0.0, 0.0, c, d],
+            [0.0, 0.0, -d, c],
+        ]
+    }
+
+    pub const IDENTITY: [[f64; 4]; 4] = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ];
+
+    /// Ideal linear polarizer transmitting along the reference axis, scaled like
+    /// [`Mueller::reflection`] so that it passes twice the light it physically would.
+    pub const LINEAR_POLARIZER: [[f64; 4]; 4] = [
+        [1.0, 1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 0.0, 0.0],
+        [0.0, 0.0, 0.0, 0.0],
+    ];
+}
+
+impl Mul<Stokes> for Mueller {
+    type Output = Stokes;
+
+    fn mul(self, rhs: Stokes) -> Stokes {
+        let black = Color::new(0.0, 0.0, 0.0);
+        let s = self
+            .m
+            .map(|row| (0..4).fold(black, |sum, j| sum + row[j] * rhs.s[j]));
+        Stokes {
+            s,
+            frame: rhs.frame,
+        }
+    }
+}
+
+/// How a scattering event changes the polarization of light: `mueller` applies to Stokes vectors
+/// against the reference axis `frame`, which is perpendicular to the light both before and after.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Polarizing {
+    pub mueller: Mueller,
+    pub frame: Vec3,
+}
+
+/// The s axis of light scattered from `incident` into `scattered` off a surface with `normal`,
+/// perpendicular to the plane of incidence.
+pub fn incidence_frame(incident: Vec3, scattered: Vec3, normal: Vec3) -> Vec3 {
+    let s = incident.cross(scattered);
+    if !s.near_zero() {
+        return s.unit_vector();
+    }
+    let s = normal.cross(incident);
+    if !s.near_zero() {
+        return s.unit_vector();
+    }
+    Onb::from_w(incident).u
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brewster_reflection_is_s_polarized() {
+        let eta = 1.5_f64;
+        let cos_i = eta.atan().cos();
+        let (r_s, r_p) = fresnel_reflection(cos_i, eta.into());
+        assert!(r_p.norm_sqr() < 1e-20);
+        let m = Mueller::uniform(Mueller::reflection(r_s, r_p));
+        let reflected = m * Stokes::unpolarized(Color::new(1.0, 1.0, 1.0));
+        assert!((reflected.s[1].r - reflected.s[0].r).abs() < 1e-9);
+
+        // Light reflected and refracted at normal incidence adds up
+        let (r_s, _) = fresnel_reflection(1.0, eta.into());
+        let (t_s, _) = fresnel_transmission(1.0, eta);
+        assert!((r_s.norm_sqr() + eta * t_s * t_s - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn conductors_reflect_per_their_complex_index() {
+        let (n, k) = (0.2, 3.0);
+        let expected = ((n - 1.0) * (n - 1.0) + k * k) / ((n + 1.0) * (n + 1.0) + k * k);
+        let reflectance = unpolarized_reflectance(1.0, Complex::new(n, k));
+        assert!((reflectance - expected).abs() < 1e-12);
+        // Total internal reflection loses nothing
+        assert!((unpolarized_reflectance(0.3, (1.0 / 1.5).into()) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn crossed_polarizers_follow_malus() {
+        let direction = Vec3::new(0.0, 0.0, -1.0);
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        let polarizer = Mueller::uniform(Mueller::LINEAR_POLARIZER);
+        let light =
+            polarizer * Stokes::unpolarized(Color::new(1.0, 1.0, 1.0)).in_frame(direction, x);
+        for degrees in [0.0_f64, 30.0, 60.0, 90.0] {
+            let (sin, cos) = degrees.to_radians().sin_cos();
+            let axis = Vec3::new(cos, sin, 0.0);
+            let passed = polarizer * light.in_frame(direction, axis);
+            // The normalized matrices pass twice the light of a real polarizer each
+            let expected = 0.5 * cos * cos;
+            assert!((passed.radiance().g / 4.0 - expected).abs() < 1e-9);
+        }
+        // Rotating back and forth changes nothing
+        let turned = light
+            .in_frame(direction, Vec3::new(1.0, 1.0, 0.0))
+            .in_frame(direction, x);
+        assert!((turned.s[1].b - light.s[1].b).abs() < 1e-12);
+        assert!(turned.s[2].b.abs() < 1e-12);
+    }
+}
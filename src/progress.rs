@@ -0,0 +1,79 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Counts finished tiles and samples from any number of render threads without locking, and
+/// reports them on stderr.
+pub struct Progress {
+    start: Instant,
+    total_tiles: usize,
+    tiles: AtomicUsize,
+    samples: AtomicU64,
+}
+
+impl Progress {
+    pub fn new(total_tiles: usize) -> Self {
+        Self {
+            start: Instant::now(),
+            total_tiles,
+            tiles: AtomicUsize::new(0),
+            samples: AtomicU64::new(0),
+        }
+    }
+
+    /// Records a finished tile that took `samples` samples and prints the progress so far.
+    pub fn tile_done(&self, samples: u64) {
+        let samples = self.samples.fetch_add(samples, Ordering::Relaxed) + samples;
+        let tiles = self.tiles.fetch_add(1, Ordering::Relaxed) + 1;
+        eprint!(
+            "\rTiles {}/{}, {}, ETA {}   ",
+            tiles,
+            self.total_tiles,
+            format_rate(samples as f64 / self.start.elapsed().as_secs_f64()),
+            format_duration(self.eta(tiles))
+        );
+    }
+
+    // Assumes the remaining tiles take as long as the finished ones did on average.
+    fn eta(&self, tiles: usize) -> Duration {
+        let remaining = self.total_tiles.saturating_sub(tiles) as f64;
+        self.start
+            .elapsed()
+            .mul_f64(remaining / tiles.max(1) as f64)
+    }
+}
+
+fn format_rate(samples_per_second: f64) -> String {
+    if !samples_per_second.is_finite() {
+        "- samples/s".to_string()
+    } else if samples_per_second >= 1e6 {
+        format!("{:.2}M samples/s", samples_per_second / 1e6)
+    } else if samples_per_second >= 1e3 {
+        format!("{:.1}k samples/s", samples_per_second / 1e3)
+    } else {
+        format!("{:.0} samples/s", samples_per_second)
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, s) => format!("{}h{:02}m{:02}s", h, m, s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_rates_and_durations() {
+        assert_eq!(format_rate(512.4), "512 samples/s");
+        assert_eq!(format_rate(25_300.0), "25.3k samples/s");
+        assert_eq!(format_rate(4_126_000.0), "4.13M samples/s");
+        assert_eq!(format_duration(Duration::from_secs(42)), "42s");
+        assert_eq!(format_duration(Duration::from_secs(125)), "2m05s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h02m03s");
+    }
+}
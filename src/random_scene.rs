@@ -0,0 +1,99 @@
+use crate::color::Color;
+use crate::environment::Environment;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::material::{Dielectric, Lambertian, Material, Metal};
+use crate::scene::Scene;
+use crate::settings::{CameraSettings, RenderSettings};
+use crate::sphere::Sphere;
+use crate::vec3::Point3;
+use rand::distributions::{Distribution, Uniform};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::Arc;
+
+/// The final scene of Ray Tracing in One Weekend: three large spheres among small random ones.
+pub fn random_scene(seed: u64) -> HittableList {
+    let mut objects: Vec<Box<dyn Hittable>> = Vec::new();
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let metal_between = Uniform::from(0.5..1.0);
+
+    let ground_material = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+    objects.push(Box::new(Sphere {
+        center: Point3::new(0.0, -1000.0, 0.0),
+        radius: 1000.0,
+        material: Arc::new(ground_material),
+    }));
+
+    let p = Point3::new(4.0, 0.2, 0.0);
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let (choose_mat, x, z) = rng.gen::<(f64, f64, f64)>();
+            let center = Point3::new(a as f64 + 0.9 * x, 0.2, b as f64 + 0.9 * z);
+
+            if (center - p).length() > 0.9 {
+                let sphere_material: Arc<dyn Material> = if choose_mat < 0.8 {
+                    let (r1, g1, b1, r2, g2, b2) = rng.gen();
+                    // diffuse
+                    let albedo = Color::new(r1, g1, b1) * Color::new(r2, g2, b2);
+                    Arc::new(Lambertian::new(albedo))
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let albedo = Color::new(
+                        metal_between.sample(&mut rng),
+                        metal_between.sample(&mut rng),
+                        metal_between.sample(&mut rng),
+                    );
+                    let fuzz = rng.gen_range(0.5..1.0);
+                    Arc::new(Metal::new(albedo, fuzz))
+                } else {
+                    //glass
+                    Arc::new(Dielectric::new(1.5))
+                };
+                objects.push(Box::new(Sphere {
+                    center,
+                    radius: 0.2,
+                    material: sphere_material,
+                }));
+            }
+        }
+    }
+
+    objects.push(Box::new(Sphere {
+        center: Point3::new(0.0, 1.0, 0.0),
+        radius: 1.0,
+        material: Arc::new(Dielectric::new(1.5)),
+    }));
+
+    objects.push(Box::new(Sphere {
+        center: Point3::new(-4.0, 1.0, 0.0),
+        radius: 1.0,
+        material: Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1))),
+    }));
+
+    objects.push(Box::new(Sphere {
+        center: Point3::new(4.0, 1.0, 0.0),
+        radius: 1.0,
+        material: Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0)),
+    }));
+
+    HittableList { objects }
+}
+
+/// The random scene with the camera and render settings of the book cover.
+pub fn default_scene(seed: u64) -> Scene {
+    Scene {
+        world: random_scene(seed),
+        camera: CameraSettings::default(),
+        settings: RenderSettings {
+            seed,
+            ..RenderSettings::default()
+        },
+        lights: HittableList {
+            objects: Vec::new(),
+        },
+        background: Environment::default(),
+    }
+}
@@ -1,14 +1,43 @@
+use crate::color::Color;
+use crate::spectrum::Wavelengths;
 use crate::vec3::{Point3, Vec3};
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
+    /// Wavelengths of a spectral path, `None` when tracing RGB.
+    pub wavelengths: Option<Wavelengths>,
+    /// Whether the path carries the polarization of light, see [`crate::polarization`].
+    pub polarized: bool,
 }
 
 impl Ray {
     pub fn new(origin: Point3, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self {
+            origin,
+            direction,
+            wavelengths: None,
+            polarized: false,
+        }
+    }
+
+    /// Ray continuing the path of this one from `origin`, at the same wavelengths.
+    pub fn spawn(&self, origin: Point3, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction,
+            ..*self
+        }
+    }
+
+    /// An RGB reflectance or radiance as the path carries it: unchanged, or upsampled to its
+    /// spectrum at the path's wavelengths.
+    pub fn spectrum(&self, rgb: Color) -> Color {
+        match &self.wavelengths {
+            Some(wavelengths) => wavelengths.upsample(rgb),
+            None => rgb,
+        }
     }
 
     pub fn at(&self, t: f64) -> Point3 {
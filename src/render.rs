@@ -0,0 +1,791 @@
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::environment::Environment;
+use crate::film::{Film, PixelStats};
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::material::Scatter;
+use crate::pdf::{power_heuristic, LightPdf, Pdf};
+use crate::polarization::{Polarizing, Stokes};
+use crate::progress::Progress;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::settings::RenderSettings;
+use crate::spectrum::Wavelengths;
+use crate::tiles::{self, Tile, TileOrder};
+use crate::vec3::Vec3;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Radiance arriving along `r`, with its polarization if `r` is polarized. Lights and the
+/// environment map, if any, are sampled directly at every diffuse bounce and combined with the
+/// BSDF sample by multiple importance sampling. `bsdf_pdf` is the density with which the
+/// previous bounce picked `r`, or `None` when light sampling could not have found it.
+fn ray_color(
+    r: &Ray,
+    world: &dyn Hittable,
+    lights: &HittableList,
+    background: &Environment,
+    depth: usize,
+    bsdf_pdf: Option<f64>,
+    sampler: &mut dyn Sampler,
+) -> Stokes {
+    let black = Color::new(0.0, 0.0, 0.0);
+    if depth == 0 {
+        return Stokes::unpolarized(black);
+    }
+    // Radiance found by the BSDF sample, weighted against light sampling having found it.
+    let weighted = |radiance: Color| match (bsdf_pdf, LightPdf::new(lights, background, r.origin)) {
+        (Some(bsdf_pdf), Some(light)) if radiance != black => {
+            radiance * power_heuristic(bsdf_pdf, light.value(r.direction))
+        }
+        _ => radiance,
+    };
+    let rec = match world.hit(r, 0.001, f64::INFINITY) {
+        Some(rec) => rec,
+        None => return Stokes::unpolarized(weighted(r.spectrum(background.color(r.direction)))),
+    };
+
+    let emitted = Stokes::unpolarized(weighted(rec.material.emitted(r, &rec)));
+    // Light arriving along `r` travels against it.
+    let toward = -r.direction;
+
+    let pdf = match rec.material.scatter(r, &rec, sampler) {
+        None => return emitted,
+        Some(Scatter::Specular {
+            attenuation,
+            scattered,
+        }) => {
+            let incoming = ray_color(
+                &scattered,
+                world,
+                lights,
+                background,
+                depth - 1,
+                None,
+                sampler,
+            );
+            let reflected = scattered_light(r, &rec, scattered.direction, incoming);
+            return emitted.plus(&(reflected * attenuation), toward);
+        }
+        Some(Scatter::Sampled { pdf }) => pdf,
+    };
+
+    // Shadow ray towards a point on a light or the environment map.
+    let mut direct = Stokes::unpolarized(black);
+    if let Some(light) = LightPdf::new(lights, background, rec.p) {
+        let direction = light.generate(sampler);
+        let light_pdf = light.value(direction);
+        let f = rec.material.eval(r, &rec, direction);
+        if light_pdf > 0.0 && f != black {
+            let shadow_ray = r.spawn(rec.p, direction);
+            let radiance = match world.hit(&shadow_ray, 0.001, f64::INFINITY) {
+                Some(light_rec) => light_rec.material.emitted(&shadow_ray, &light_rec),
+                None if background.sampled_map().is_some() => {
+                    shadow_ray.spectrum(background.color(direction))
+                }
+                None => black,
+            };
+            // The BSDF sample of the last bounce finds nothing, so it leaves the light to this one.
+            let weight = if depth > 1 {
+                power_heuristic(light_pdf, pdf.value(direction)) / light_pdf
+            } else {
+                1.0 / light_pdf
+            };
+            let incoming = Stokes::unpolarized(radiance);
+            direct = scattered_light(r, &rec, direction, incoming) * (f * weight);
+        }
+    }
+
+    let direction = pdf.generate(sampler);
+    let pdf_value = pdf.value(direction);
+    if pdf_value <= 0.0 {
+        return emitted.plus(&direct, toward);
+    }
+    let scattered = r.spawn(rec.p, direction);
+    let f = rec.material.eval(r, &rec, direction) * (1.0 / pdf_value);
+    let indirect = ray_color(
+        &scattered,
+        world,
+        lights,
+        background,
+        depth - 1,
+        Some(pdf_value),
+        sampler,
+    );
+    let indirect = scattered_light(r, &rec, direction, indirect) * f;
+    emitted.plus(&direct, toward).plus(&indirect, toward)
+}
+
+/// `incoming` light arriving along `direction` at `rec` as it leaves towards the origin of `r`:
+/// polarized by the material on polarized paths, otherwise depolarized. The intensity is left to
+/// the caller.
+fn scattered_light(r: &Ray, rec: &HitRecord, direction: Vec3, incoming: Stokes) -> Stokes {
+    if r.polarized {
+        if let Some(Polarizing { mueller, frame }) = rec.material.polarization(r, rec, direction) {
+            return mueller * incoming.in_frame(-direction, frame);
+        }
+    }
+    Stokes::unpolarized(incoming.radiance())
+}
+
+/// Traces the samples of a frame.
+pub struct Renderer<'a> {
+    world: &'a dyn Hittable,
+    lights: &'a HittableList,
+    background: &'a Environment,
+    camera: &'a Camera,
+    settings: RenderSettings,
+    interrupted: Option<&'a AtomicBool>,
+    report_progress: bool,
+}
+
+impl<'a> Renderer<'a> {
+    /// `lights` are the emissive objects of `world`, sampled directly at every bounce.
+    pub fn new(
+        world: &'a dyn Hittable,
+        lights: &'a HittableList,
+        background: &'a Environment,
+        camera: &'a Camera,
+        settings: RenderSettings,
+    ) -> Self {
+        Self {
+            world,
+            lights,
+            background,
+            camera,
+            settings,
+            interrupted: None,
+            report_progress: false,
+        }
+    }
+
+    /// Stops sampling as soon as `flag` is set, see [`Renderer::render_pass`].
+    pub fn interrupted_by(mut self, flag: &'a AtomicBool) -> Self {
+        self.interrupted = Some(flag);
+        self
+    }
+
+    /// Reports finished tiles, samples per second and the time left on stderr.
+    pub fn with_progress(mut self) -> Self {
+        self.report_progress = true;
+        self
+    }
+
+    /// Renders the whole frame in a single pass.
+    pub fn render(&self) -> Film {
+        let RenderSettings {
+            image_width,
+            image_height,
+            samples_per_pixel,
+            ..
+        } = self.settings;
+        let mut film = Film::new(image_width, image_height);
+        let tiles = tiles::tiles(image_width, image_height, 32, TileOrder::Spiral);
+        self.render_pass(&mut film, &tiles, samples_per_pixel);
+        film
+    }
+
+    /// Samples every pixel of `film` until it holds `samples` samples or has converged. Since
+    /// every sample only depends on its pixel and index, rendering in passes of increasing
+    /// `samples` ends in the same film as a single pass. Returns early, leaving pixels with
+    /// fewer samples, once the render is
+    /// [interrupted](Renderer::interrupted_by).
+    pub fn render_pass(&self, film: &mut Film, tiles: &[Tile], samples: usize) {
+        let film = Mutex::new(film);
+        let next_tile = AtomicUsize::new(0);
+        let progress = self.report_progress.then(|| Progress::new(tiles.len()));
+
+        // Every thread of the pool takes the next tile in order until none are left. The film
+        // is only locked to copy a tile out before sampling it and back in afterwards.
+        rayon::scope(|scope| {
+            for _ in 0..rayon::current_num_threads() {
+                scope.spawn(|_| {
+                    while let Some(&tile) = tiles.get(next_tile.fetch_add(1, Ordering::Relaxed)) {
+                        let mut pixels = film.lock().unwrap().read_tile(tile);
+                        let samples_taken = self.render_tile(tile, &mut pixels, samples);
+                        film.lock().unwrap().write_tile(tile, &pixels);
+                        if let Some(progress) = &progress {
+                            progress.tile_done(samples_taken);
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    /// Samples the pixels of `tile`, as read by [`Film::read_tile`], like
+    /// [`Renderer::render_pass`]. Returns the number of samples taken.
+    pub(crate) fn render_tile(&self, tile: Tile, pixels: &mut [PixelStats], samples: usize) -> u64 {
+        let mut samples_taken = 0;
+        for (k, stats) in (0..).zip(pixels.iter_mut()) {
+            let (x, y) = (tile.x + k % tile.width, tile.y + k / tile.width);
+            let before = stats.count;
+            self.sample_pixel((x, self.settings.image_height - 1 - y), stats, samples);
+            samples_taken += stats.count - before;
+        }
+        samples_taken
+    }
+
+    fn sample_pixel(&self, (i, j): (u32, u32), stats: &mut PixelStats, samples: usize) {
+        let RenderSettings {
+            image_width,
+            image_height,
+            samples_per_pixel,
+            min_samples_per_pixel,
+            noise_threshold,
+            max_depth,
+            seed,
+            sampler,
+            spectral,
+            polarized,
+        } = self.settings;
+        // Without a noise threshold every pixel takes the full sample count.
+        let min_samples = if noise_threshold > 0.0 {
+            min_samples_per_pixel.min(samples_per_pixel)
+        } else {
+            samples_per_pixel
+        };
+
+        for sample in stats.count..samples as u64 {
+            if self
+                .interrupted
+                .is_some_and(|flag| flag.load(Ordering::Relaxed))
+            {
+                return;
+            }
+            if sample >= min_samples as u64 && stats.relative_error() < noise_threshold {
+                break;
+            }
+            let mut sampler = sampler.create(seed, (i, j), sample, samples_per_pixel);
+            let (ir, ij) = sampler.get_2d();
+            let u = (i as f64 + ir) / (image_width - 1).max(1) as f64;
+            let v = (j as f64 + ij) / (image_height - 1).max(1) as f64;
+            let mut r = self.camera.get_ray(u, v, &mut *sampler);
+            if spectral {
+                r.wavelengths = Some(Wavelengths::sample(sampler.get_1d()));
+            }
+            r.polarized = polarized;
+            let stokes = ray_color(
+                &r,
+                self.world,
+                self.lights,
+                self.background,
+                max_depth,
+                None,
+                &mut *sampler,
+            );
+            let rgb = |values: Color| match &r.wavelengths {
+                Some(wavelengths) => wavelengths.to_rgb(values),
+                None => values,
+            };
+            if polarized {
+                // Measured against the horizontal of the image.
+                let stokes = stokes.in_frame(-r.direction, self.camera.right());
+                stats.add_polarized(rgb(stokes.s[0]), rgb(stokes.s[1]), rgb(stokes.s[2]));
+            } else {
+                stats.add(rgb(stokes.radiance()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::environment::EnvironmentMap;
+    use crate::image::Framebuffer;
+    use crate::random_scene::random_scene;
+    use crate::sampler::IndependentSampler;
+    use crate::scene::{self, Scene};
+    use crate::settings::CameraSettings;
+    use crate::vec3::{Point3, Vec3};
+    use std::f64::consts::PI;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    // Mean and variance of the red channel of `n` estimates of the light reaching the floor, along
+    // paths of up to `depth` rays.
+    fn estimate(scene: &Scene, lights: &HittableList, depth: usize, n: usize) -> (f64, f64) {
+        estimate_color(scene, lights, depth, n, false, |color| color.r)
+    }
+
+    fn estimate_color(
+        scene: &Scene,
+        lights: &HittableList,
+        depth: usize,
+        n: usize,
+        spectral: bool,
+        channel: impl Fn(Color) -> f64,
+    ) -> (f64, f64) {
+        let world: &dyn Hittable = &scene.world;
+        let samples: Vec<f64> = (0..n)
+            .map(|sample| {
+                let mut sampler = IndependentSampler::new(0, (0, 0), sample as u64);
+                let mut r = Ray::new(Point3::new(0.3, 1.0, 0.2), Vec3::new(0.0, -1.0, 0.0));
+                if spectral {
+                    r.wavelengths = Some(Wavelengths::sample(sampler.get_1d()));
+                }
+                let radiance = ray_color(
+                    &r,
+                    world,
+                    lights,
+                    &scene.background,
+                    depth,
+                    None,
+                    &mut sampler,
+                )
+                .radiance();
+                channel(match &r.wavelengths {
+                    Some(wavelengths) => wavelengths.to_rgb(radiance),
+                    None => radiance,
+                })
+            })
+            .collect();
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n as f64;
+        (mean, variance)
+    }
+
+    #[test]
+    fn light_sampling_matches_bsdf_sampling() {
+        let scene = scene::parse(
+            r#"
+[background]
+type = "Solid"
+color = [0, 0, 0]
+
+[materials.floor]
+type = "Lambertian"
+albedo = [0.5, 0.5, 0.5]
+
+[materials.light]
+type = "DiffuseLight"
+emit = [20, 20, 20]
+
+[[objects]]
+type = "Quad"
+q = [-5, 0, -5]
+u = [0, 0, 10]
+v = [10, 0, 0]
+material = "floor"
+
+[[objects]]
+type = "Quad"
+q = [-0.25, 2, -0.25]
+u = [0.5, 0, 0]
+v = [0, 0, 0.5]
+material = "light"
+"#,
+            Path::new("test.toml"),
+        )
+        .unwrap();
+        let no_lights = HittableList {
+            objects: Vec::new(),
+        };
+
+        let (bsdf_mean, bsdf_variance) = estimate(&scene, &no_lights, 2, 400_000);
+        let (mis_mean, mis_variance) = estimate(&scene, &scene.lights, 2, 20_000);
+        let error = (bsdf_variance / 400_000.0 + mis_variance / 20_000.0).sqrt();
+        assert!(
+            (bsdf_mean - mis_mean).abs() < 4.0 * error,
+            "bsdf {} mis {} ± {}",
+            bsdf_mean,
+            mis_mean,
+            error
+        );
+        assert!(mis_variance * 10.0 < bsdf_variance);
+
+        // At the last bounce light sampling alone finds the light, which BSDF samples would have
+        // found most of the time under a light this wide
+        let scene = floor_under_light(
+            "type = \"Lambertian\"\nalbedo = [0.5, 0.5, 0.5]",
+            "[1, 1, 1]",
+            8.0,
+        );
+        let (bsdf_mean, bsdf_variance) = estimate(&scene, &no_lights, 2, 100_000);
+        let (last_mean, last_variance) = estimate(&scene, &scene.lights, 1, 20_000);
+        let error = (bsdf_variance / 100_000.0 + last_variance / 20_000.0).sqrt();
+        assert!(
+            (bsdf_mean - last_mean).abs() < 4.0 * error,
+            "bsdf {} last bounce {} ± {}",
+            bsdf_mean,
+            last_mean,
+            error
+        );
+    }
+
+    // A 10 by 10 floor of the material whose TOML table body is `floor`, lying under the rays of
+    // `estimate` beneath a black sky, lit by a square light of side `size` two units up.
+    fn floor_under_light(floor: &str, emit: &str, size: f64) -> Scene {
+        scene::parse(
+            &format!(
+                r#"
+[background]
+type = "Solid"
+color = [0, 0, 0]
+
+[materials.floor]
+{}
+
+[materials.light]
+type = "DiffuseLight"
+emit = {}
+
+[[objects]]
+type = "Quad"
+q = [-5, 0, -5]
+u = [0, 0, 10]
+v = [10, 0, 0]
+material = "floor"
+
+[[objects]]
+type = "Quad"
+q = [{corner}, 2, {corner}]
+u = [{size}, 0, 0]
+v = [0, 0, {size}]
+material = "light"
+"#,
+                floor,
+                emit,
+                corner = -size / 2.0,
+                size = size
+            ),
+            Path::new("test.toml"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn rough_materials_weigh_light_and_bsdf_samples_alike() {
+        let floors = [
+            "type = \"Conductor\"\nmetal = \"Gold\"\nroughness = 0.5",
+            "type = \"RoughDielectric\"\nir = 1.5\nroughness = 0.4",
+        ];
+        for floor in floors {
+            let scene = floor_under_light(floor, "[20, 20, 20]", 0.5);
+            let no_lights = HittableList {
+                objects: Vec::new(),
+            };
+
+            let (bsdf_mean, bsdf_variance) = estimate(&scene, &no_lights, 2, 200_000);
+            let (mis_mean, mis_variance) = estimate(&scene, &scene.lights, 2, 50_000);
+            let error = (bsdf_variance / 200_000.0 + mis_variance / 50_000.0).sqrt();
+            assert!(bsdf_mean > 0.0);
+            assert!(
+                (bsdf_mean - mis_mean).abs() < 4.0 * error,
+                "{}: bsdf {} mis {} ± {}",
+                floor,
+                bsdf_mean,
+                mis_mean,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn polarizers_block_light_reflected_at_brewster_angle() {
+        // Glass over a black floor under a white sky, seen at Brewster's angle through a
+        // polarizer that only covers the way in.
+        let glass = |polarizer: &str| {
+            scene::parse(
+                &format!(
+                    r#"
+[background]
+type = "Solid"
+color = [1, 1, 1]
+
+[materials.glass]
+type = "Dielectric"
+ir = 1.5
+
+[materials.black]
+type = "Lambertian"
+albedo = [0, 0, 0]
+
+[materials.polarizer]
+type = "LinearPolarizer"
+axis = {}
+
+[[objects]]
+type = "Quad"
+q = [-5, 0, -5]
+u = [0, 0, 10]
+v = [10, 0, 0]
+material = "glass"
+
+[[objects]]
+type = "Quad"
+q = [-5, -1, -5]
+u = [0, 0, 10]
+v = [10, 0, 0]
+material = "black"
+
+[[objects]]
+type = "Quad"
+q = [-5, 0.5, -5]
+u = [0, 0, 10]
+v = [4.9, 0, 0]
+material = "polarizer"
+"#,
+                    polarizer
+                ),
+                Path::new("test.toml"),
+            )
+            .unwrap()
+        };
+        let (sin, cos) = 1.5_f64.atan().sin_cos();
+        let trace = |scene: &Scene, polarized: bool| {
+            let world: &dyn Hittable = &scene.world;
+            let mut sum = Stokes::unpolarized(Color::new(0.0, 0.0, 0.0));
+            let direction = Vec3::new(sin, -cos, 0.0);
+            for sample in 0..500 {
+                let mut sampler = IndependentSampler::new(0, (0, 0), sample);
+                let mut r = Ray::new(Point3::new(-2.0 * sin, 2.0 * cos, 0.0), direction);
+                r.polarized = polarized;
+                let stokes = ray_color(
+                    &r,
+                    world,
+                    &scene.lights,
+                    &scene.background,
+                    3,
+                    None,
+                    &mut sampler,
+                );
+                sum = sum.plus(&stokes, -direction);
+            }
+            sum.in_frame(-direction, Vec3::new(0.0, 0.0, 1.0))
+        };
+
+        // Reflected light is polarized along the glass, and passes a polarizer turned that way
+        let along = trace(&glass("[0, 0, 1]"), true);
+        assert!(along.s[0].g > 10.0);
+        assert!((along.s[1].g - along.s[0].g).abs() < 1e-9);
+        // Turned across, the polarizer blocks it
+        let across = trace(&glass("[1, 0, 0]"), true);
+        assert!(across.s[0].g.abs() < 1e-9);
+        // Unpolarized paths see the polarizer as a neutral filter, whichever way it is turned
+        let unpolarized = trace(&glass("[1, 0, 0]"), false);
+        assert!(unpolarized.s[0].g > 0.0);
+        assert_eq!(unpolarized.s[0], trace(&glass("[0, 0, 1]"), false).s[0]);
+        assert_eq!(unpolarized.s[1].g, 0.0);
+    }
+
+    #[test]
+    fn spectral_mode_matches_rgb() {
+        let scene = scene::parse(
+            r#"
+[background]
+type = "Gradient"
+bottom = [0.2, 0.2, 0.2]
+top = [0.3, 0.4, 0.8]
+
+[materials.floor]
+type = "Lambertian"
+albedo = [0.7, 0.35, 0.15]
+
+[materials.light]
+type = "DiffuseLight"
+emit = [12, 10, 8]
+
+[[objects]]
+type = "Quad"
+q = [-5, 0, -5]
+u = [0, 0, 10]
+v = [10, 0, 0]
+material = "floor"
+
+[[objects]]
+type = "Quad"
+q = [-0.5, 2, -0.5]
+u = [1, 0, 0]
+v = [0, 0, 1]
+material = "light"
+"#,
+            Path::new("test.toml"),
+        )
+        .unwrap();
+        let channels: [fn(Color) -> f64; 3] = [|c| c.r, |c| c.g, |c| c.b];
+        for channel in channels {
+            let n = 20_000;
+            let (rgb, _) = estimate_color(&scene, &scene.lights, 2, n, false, channel);
+            let (spectral, variance) = estimate_color(&scene, &scene.lights, 2, n, true, channel);
+            let error = (variance / n as f64).sqrt();
+            assert!(
+                (spectral - rgb).abs() < 0.05 * rgb + 4.0 * error,
+                "rgb {} spectral {} ± {}",
+                rgb,
+                spectral,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn environment_map_lighting_converges() {
+        let mut scene = scene::parse(
+            r#"
+[materials.floor]
+type = "Lambertian"
+albedo = [0.5, 0.5, 0.5]
+
+[[objects]]
+type = "Quad"
+q = [-50, 0, -50]
+u = [0, 0, 100]
+v = [100, 0, 0]
+material = "floor"
+"#,
+            Path::new("test.toml"),
+        )
+        .unwrap();
+        // Dim sky with a sun a quarter of the way down from the zenith.
+        let (width, height) = (32, 16);
+        let pixels = (0..width * height)
+            .map(|i| match (i % width, i / width) {
+                (5, 4) => Color::new(400.0, 400.0, 400.0),
+                _ => Color::new(0.3, 0.3, 0.3),
+            })
+            .collect();
+        let sky = Framebuffer::from_pixels(width, height, pixels);
+        scene.background = Environment::Map(Arc::new(EnvironmentMap::new(sky, 40.0, 1.5)));
+
+        // Irradiance of the floor, summed over a fine grid of the upper hemisphere.
+        let steps = 2000;
+        let mut irradiance = 0.0;
+        for i in 0..steps / 2 {
+            let theta = PI * (i as f64 + 0.5) / steps as f64;
+            for j in 0..steps {
+                let phi = 2.0 * PI * (j as f64 + 0.5) / steps as f64;
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                let solid_angle = theta.sin() * (PI / steps as f64) * (2.0 * PI / steps as f64);
+                irradiance += scene.background.color(direction).r * theta.cos() * solid_angle;
+            }
+        }
+        let exact = 0.5 / PI * irradiance;
+
+        let n = 20_000;
+        let (mean, variance) = estimate(&scene, &scene.lights, 2, n);
+        let error = (variance / n as f64).sqrt();
+        assert!(
+            (mean - exact).abs() < 4.0 * error,
+            "{} {} ± {}",
+            exact,
+            mean,
+            error
+        );
+        assert!(error < 0.01 * exact, "{} ± {}", mean, error);
+    }
+
+    #[test]
+    fn render_independent_of_thread_count() {
+        let world = random_scene(3);
+        let no_lights = HittableList {
+            objects: Vec::new(),
+        };
+        let background = Environment::default();
+        let camera = CameraSettings::default();
+        let cam = Camera::new(
+            camera.look_from,
+            camera.look_at,
+            camera.vup,
+            camera.vfov,
+            1.5,
+            camera.aperture,
+            camera.focus_dist,
+        );
+        let settings = RenderSettings {
+            image_width: 24,
+            image_height: 16,
+            samples_per_pixel: 4,
+            max_depth: 8,
+            seed: 5,
+            ..RenderSettings::default()
+        };
+        let tiles = tiles::tiles(24, 16, 5, TileOrder::Spiral);
+        let render_with = |threads: usize, seed: u64| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let settings = RenderSettings { seed, ..settings };
+            let renderer = Renderer::new(&world, &no_lights, &background, &cam, settings);
+            let mut film = Film::new(24, 16);
+            pool.install(|| renderer.render_pass(&mut film, &tiles, 4));
+            film.pixels
+        };
+
+        let single = render_with(1, 5);
+        assert_eq!(single, render_with(4, 5));
+        assert_ne!(single, render_with(4, 6));
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_in_easy_regions() {
+        let world = random_scene(3);
+        let no_lights = HittableList {
+            objects: Vec::new(),
+        };
+        let background = Environment::default();
+        let camera = CameraSettings::default();
+        let cam = Camera::new(
+            camera.look_from,
+            camera.look_at,
+            camera.vup,
+            camera.vfov,
+            1.5,
+            camera.aperture,
+            camera.focus_dist,
+        );
+        let settings = RenderSettings {
+            image_width: 24,
+            image_height: 16,
+            samples_per_pixel: 64,
+            min_samples_per_pixel: 8,
+            noise_threshold: 0.02,
+            max_depth: 8,
+            ..RenderSettings::default()
+        };
+        let tiles = tiles::tiles(24, 16, 8, TileOrder::Hilbert);
+        let renderer = Renderer::new(&world, &no_lights, &background, &cam, settings);
+        let mut film = Film::new(24, 16);
+        renderer.render_pass(&mut film, &tiles, 64);
+        let counts: Vec<u64> = film.pixels.iter().map(|stats| stats.count).collect();
+        assert!(counts.iter().all(|&count| (8..=64).contains(&count)));
+        // The sky in the top row converges at once, the noisy spheres need every sample.
+        assert!(counts[..24].iter().all(|&count| count == 8));
+        assert!(counts.contains(&64));
+
+        // Passes pick up where the previous one stopped and end in the same film.
+        let mut progressive = Film::new(24, 16);
+        for samples in [8, 20, 40, 64] {
+            renderer.render_pass(&mut progressive, &tiles, samples);
+        }
+        assert_eq!(progressive.pixels, film.pixels);
+
+        // So do renders resumed from a checkpoint.
+        let mut checkpoint = Vec::new();
+        let mut partial = Film::new(24, 16);
+        renderer.render_pass(&mut partial, &tiles, 20);
+        partial.write_checkpoint("test", &mut checkpoint).unwrap();
+        let mut resumed = Film::read_checkpoint("test", &mut &checkpoint[..]).unwrap();
+        renderer.render_pass(&mut resumed, &tiles, 64);
+        assert_eq!(resumed.pixels, film.pixels);
+
+        // A single sample says nothing about the noise, so no pixel stops after one.
+        let settings = RenderSettings {
+            min_samples_per_pixel: 1,
+            ..settings
+        };
+        let renderer = Renderer::new(&world, &no_lights, &background, &cam, settings);
+        let mut film = Film::new(24, 16);
+        renderer.render_pass(&mut film, &tiles, 64);
+        assert!(film.pixels.iter().all(|stats| stats.count >= 2));
+        assert!(film.pixels.iter().any(|stats| stats.count == 64));
+    }
+}
@@ -0,0 +1,593 @@
+use std::sync::OnceLock;
+
+/// Source of the random numbers used to render one sample of one pixel.
+///
+/// Samplers are created from the render seed, the pixel and the sample index, so an image comes
+/// out bit-identical however the work is spread over threads. Every call consumes the next
+/// dimension of the sample, so the camera and each bounce see a consistent set of dimensions.
+pub trait Sampler {
+    /// Next dimension, in [0, 1).
+    fn get_1d(&mut self) -> f64;
+
+    /// Next pair of dimensions, in [0, 1)².
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl SamplerKind {
+    /// Sampler for `sample` of `samples_per_pixel` at `pixel`. Sample indices past
+    /// `samples_per_pixel` are allowed and continue the sequence.
+    pub fn create(
+        self,
+        seed: u64,
+        pixel: (u32, u32),
+        sample: u64,
+        samples_per_pixel: usize,
+    ) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed, pixel, sample)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(
+                seed,
+                pixel,
+                sample,
+                samples_per_pixel,
+            )),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed, pixel, sample)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed, pixel, sample)),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::new(
+                seed,
+                pixel,
+                sample,
+                samples_per_pixel,
+            )),
+        }
+    }
+}
+
+/// Uniform random numbers from PCG32, O'Neill 2014.
+#[derive(Debug, Clone)]
+pub struct IndependentSampler {
+    state: u64,
+    increment: u64,
+}
+
+const MULTIPLIER: u64 = 6364136223846793005;
+
+impl IndependentSampler {
+    pub fn new(seed: u64, pixel: (u32, u32), sample: u64) -> Self {
+        Self::from_key(splitmix64(pixel_key(seed, pixel) ^ sample))
+    }
+
+    fn from_key(key: u64) -> Self {
+        let mut sampler = Self {
+            state: 0,
+            increment: splitmix64(key) << 1 | 1,
+        };
+        sampler.next_u32();
+        sampler.state = sampler.state.wrapping_add(key);
+        sampler.next_u32();
+        sampler
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn get_1d(&mut self) -> f64 {
+        let bits = (self.next_u32() as u64) << 21 | (self.next_u32() as u64) >> 11;
+        bits as f64 / (1u64 << 53) as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+/// Jittered strata, shuffled independently for every dimension so that they do not correlate.
+/// Each round of `samples_per_pixel` samples covers all strata once.
+pub struct StratifiedSampler {
+    key: u64,
+    index: u32,
+    strata: u32,
+    dimension: u64,
+    jitter: IndependentSampler,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, pixel: (u32, u32), sample: u64, samples_per_pixel: usize) -> Self {
+        let strata = samples_per_pixel.clamp(1, u32::MAX as usize) as u64;
+        let round = sample / strata;
+        Self {
+            key: splitmix64(pixel_key(seed, pixel) ^ round),
+            index: (sample % strata) as u32,
+            strata: strata as u32,
+            dimension: 0,
+            jitter: IndependentSampler::new(seed, pixel, sample),
+        }
+    }
+
+    // Stratum of this sample among `count` in the current dimension.
+    fn stratum(&mut self, count: u32) -> u32 {
+        let seed = splitmix64(self.key ^ self.dimension) as u32;
+        self.dimension += 1;
+        permutation_element(self.index, count, seed)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn get_1d(&mut self) -> f64 {
+        let stratum = self.stratum(self.strata);
+        (stratum as f64 + self.jitter.get_1d()) / self.strata as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        // Smallest grid with at least one cell per sample.
+        let nx = (self.strata as f64).sqrt().ceil() as u32;
+        let ny = self.strata.div_ceil(nx);
+        let cell = self.stratum(nx * ny);
+        let (jx, jy) = self.jitter.get_2d();
+        (
+            ((cell % nx) as f64 + jx) / nx as f64,
+            ((cell / nx) as f64 + jy) / ny as f64,
+        )
+    }
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// Halton sequence with random digit permutations, one prime base per dimension. Dimensions past
+/// the table of primes fall back to independent samples.
+pub struct HaltonSampler {
+    key: u64,
+    index: u64,
+    dimension: usize,
+    fallback: IndependentSampler,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64, pixel: (u32, u32), sample: u64) -> Self {
+        Self {
+            key: pixel_key(seed, pixel),
+            index: sample,
+            dimension: 0,
+            fallback: IndependentSampler::new(seed, pixel, sample),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match PRIMES.get(dimension) {
+            Some(&base) => {
+                let seed = splitmix64(self.key ^ dimension as u64) as u32;
+                scrambled_radical_inverse(base, self.index, seed)
+            }
+            None => self.fallback.get_1d(),
+        }
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+// Digits of `index` in `base` mirrored around the radix point, each digit position permuted
+// with its own seed. Scrambled zero digits keep contributing, so all digits down to the f64
+// precision are generated.
+fn scrambled_radical_inverse(base: u32, mut index: u64, seed: u32) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0;
+    let mut result = 0.0;
+    let mut position = 0u64;
+    while 1.0 - (base - 1) as f64 * inv_base_m < 1.0 {
+        let digit = (index % base as u64) as u32;
+        let digit_seed = splitmix64(seed as u64 ^ position << 32) as u32;
+        inv_base_m *= inv_base;
+        result += permutation_element(digit, base, digit_seed) as f64 * inv_base_m;
+        index /= base as u64;
+        position += 1;
+    }
+    result.min(ONE_MINUS_EPSILON)
+}
+
+/// Owen-scrambled Sobol points, padded from 2D: every pair of dimensions uses its own shuffle of
+/// the sample index and its own scrambling, Burley 2020.
+pub struct SobolSampler {
+    key: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64, pixel: (u32, u32), sample: u64) -> Self {
+        Self {
+            key: pixel_key(seed, pixel),
+            index: sample as u32,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn get_1d(&mut self) -> f64 {
+        self.get_2d().0
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let seed = splitmix64(self.key ^ self.dimension);
+        self.dimension += 1;
+        let index = nested_uniform_scramble(self.index, seed as u32);
+        let x = nested_uniform_scramble(sobol_x(index), (seed >> 32) as u32);
+        let y = nested_uniform_scramble(sobol_y(index), splitmix64(seed) as u32);
+        (to_unit(x), to_unit(y))
+    }
+}
+
+// First Sobol dimension, the base 2 van der Corput sequence.
+fn sobol_x(index: u32) -> u32 {
+    index.reverse_bits()
+}
+
+// Second Sobol dimension, generated by the primitive polynomial x + 1.
+fn sobol_y(mut index: u32) -> u32 {
+    let mut result = 0;
+    let mut direction = 1u32 << 31;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+// Owen scrambling of the bits of `x`, Laine and Karras 2011 as refined by Burley 2020.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+fn to_unit(bits: u32) -> f64 {
+    bits as f64 / (1u64 << 32) as f64
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// Padded rank-1 lattice points rotated by a blue noise mask, so that the error of neighbouring
+/// pixels is decorrelated and looks like high frequency noise, Georgiev and Fajardo 2016.
+pub struct BlueNoiseSampler {
+    key: u64,
+    pixel: (u32, u32),
+    index: u32,
+    round: u64,
+    count: u32,
+    dimension: u64,
+}
+
+impl BlueNoiseSampler {
+    pub fn new(seed: u64, pixel: (u32, u32), sample: u64, samples_per_pixel: usize) -> Self {
+        let count = samples_per_pixel.clamp(1, u32::MAX as usize) as u64;
+        Self {
+            key: splitmix64(seed),
+            pixel,
+            index: (sample % count) as u32,
+            round: sample / count,
+            count: count as u32,
+            dimension: 0,
+        }
+    }
+
+    // Index shuffled for the current dimension and the mask offset of that dimension.
+    fn next(&mut self) -> (f64, u64) {
+        let key = splitmix64(self.key ^ self.dimension);
+        self.dimension += 1;
+        let shuffled = permutation_element(self.index, self.count, (key ^ self.round) as u32);
+        (shuffled as f64, key)
+    }
+
+    fn mask(&self, key: u64) -> f64 {
+        let n = BLUE_NOISE_SIZE as u64;
+        let x = (self.pixel.0 as u64 + (key >> 32) % n) % n;
+        let y = (self.pixel.1 as u64 + (key >> 40) % n) % n;
+        blue_noise_mask()[(y * n + x) as usize]
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn get_1d(&mut self) -> f64 {
+        // Golden ratio sequence.
+        let (index, key) = self.next();
+        fract(0.5 + index * 0.618_033_988_749_894_9 + self.mask(key))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        // R2 sequence from the plastic constant, Roberts 2018.
+        let (index, key) = self.next();
+        let g = 1.324_717_957_244_746;
+        let x = fract(0.5 + index / g + self.mask(key));
+        let y = fract(0.5 + index / (g * g) + self.mask(splitmix64(key)));
+        (x, y)
+    }
+}
+
+fn fract(x: f64) -> f64 {
+    (x - x.floor()).min(ONE_MINUS_EPSILON)
+}
+
+// Ranks of a void-and-cluster blue noise pattern scaled to [0, 1), Ulichney 1993. Built on
+// first use.
+fn blue_noise_mask() -> &'static [f64] {
+    static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+    MASK.get_or_init(|| void_and_cluster(BLUE_NOISE_SIZE, 0x5eed))
+}
+
+fn void_and_cluster(size: usize, seed: u64) -> Vec<f64> {
+    let n = size * size;
+    // Gaussian weight of each toroidal offset, sigma 1.5.
+    let kernel: Vec<f64> = (0..n)
+        .map(|i| {
+            let wrap = |d: usize| d.min(size - d) as f64;
+            let (dx, dy) = (wrap(i % size), wrap(i / size));
+            (-(dx * dx + dy * dy) / (2.0 * 1.5 * 1.5)).exp()
+        })
+        .collect();
+    let offset = |a: usize, b: usize| {
+        let dx = (a % size + size - b % size) % size;
+        let dy = (a / size + size - b / size) % size;
+        dy * size + dx
+    };
+
+    let mut ones = vec![false; n];
+    let mut energy = vec![0.0; n];
+    let toggle = |ones: &mut Vec<bool>, energy: &mut Vec<f64>, p: usize| {
+        ones[p] = !ones[p];
+        let sign = if ones[p] { 1.0 } else { -1.0 };
+        for (q, e) in energy.iter_mut().enumerate() {
+            *e += sign * kernel[offset(q, p)];
+        }
+    };
+    let tightest_cluster = |ones: &[bool], energy: &[f64]| {
+        (0..n)
+            .filter(|&p| ones[p])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+    let largest_void = |ones: &[bool], energy: &[f64]| {
+        (0..n)
+            .filter(|&p| !ones[p])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+
+    // Random initial pattern of about a tenth of the pixels, relaxed by moving the point in the
+    // tightest cluster to the largest void until that point is already the best placed one.
+    let mut rng = IndependentSampler::from_key(seed);
+    let initial = n / 10;
+    let mut placed = 0;
+    while placed < initial {
+        let p = ((rng.get_1d() * n as f64) as usize).min(n - 1);
+        if !ones[p] {
+            toggle(&mut ones, &mut energy, p);
+            placed += 1;
+        }
+    }
+    loop {
+        let cluster = tightest_cluster(&ones, &energy);
+        toggle(&mut ones, &mut energy, cluster);
+        let void = largest_void(&ones, &energy);
+        toggle(&mut ones, &mut energy, void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0; n];
+    // Rank the initial points by removing them from the tightest clusters first.
+    let (initial_ones, initial_energy) = (ones.clone(), energy.clone());
+    for r in (0..initial).rev() {
+        let cluster = tightest_cluster(&ones, &energy);
+        toggle(&mut ones, &mut energy, cluster);
+        rank[cluster] = r;
+    }
+    // Then fill the largest voids until every pixel has a rank.
+    let (mut ones, mut energy) = (initial_ones, initial_energy);
+    for r in initial..n {
+        let void = largest_void(&ones, &energy);
+        toggle(&mut ones, &mut energy, void);
+        rank[void] = r;
+    }
+    rank.into_iter()
+        .map(|r| (r as f64 + 0.5) / n as f64)
+        .collect()
+}
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+// Element `i` of a random permutation of `0..len` chosen by `seed`, Kensler 2013.
+fn permutation_element(i: u32, len: u32, seed: u32) -> u32 {
+    if len <= 1 {
+        return 0;
+    }
+    let mut w = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    let p = seed;
+    let mut i = i;
+    // Cycle walk until the hash lands inside the range.
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % len
+}
+
+fn pixel_key(seed: u64, (x, y): (u32, u32)) -> u64 {
+    splitmix64(splitmix64(seed) ^ ((y as u64) << 32 | x as u64))
+}
+
+// Finalizer of SplitMix64, Steele et al. 2014, to spread nearby keys over the whole state space.
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 5] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+        SamplerKind::BlueNoise,
+    ];
+
+    fn stream(seed: u64, pixel: (u32, u32), sample: u64) -> Vec<f64> {
+        let mut sampler = IndependentSampler::new(seed, pixel, sample);
+        (0..8).map(|_| sampler.get_1d()).collect()
+    }
+
+    #[test]
+    fn stream_depends_on_seed_pixel_and_sample() {
+        let base = stream(1, (3, 4), 5);
+        assert_eq!(base, stream(1, (3, 4), 5));
+        assert_ne!(base, stream(2, (3, 4), 5));
+        assert_ne!(base, stream(1, (4, 3), 5));
+        assert_ne!(base, stream(1, (3, 4), 6));
+    }
+
+    #[test]
+    fn permutation_is_a_bijection() {
+        for len in [1, 2, 7, 64, 100] {
+            let mut seen: Vec<u32> = (0..len)
+                .map(|i| permutation_element(i, len, 1234))
+                .collect();
+            seen.sort_unstable();
+            assert_eq!(seen, (0..len).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn blue_noise_mask_is_a_permutation_of_ranks() {
+        let n = BLUE_NOISE_SIZE * BLUE_NOISE_SIZE;
+        let mut ranks: Vec<usize> = blue_noise_mask()
+            .iter()
+            .map(|v| (v * n as f64) as usize)
+            .collect();
+        ranks.sort_unstable();
+        assert_eq!(ranks, (0..n).collect::<Vec<_>>());
+    }
+
+    // Squared error of estimating the integral of a smooth function over the square with
+    // `samples` samples, averaged over many pixels. The dimensions before the pair used are
+    // skipped to check that padded dimensions stay well distributed.
+    fn mean_squared_error(kind: SamplerKind, skip: usize, samples: usize) -> f64 {
+        let f = |x: f64, y: f64| (x * 3.0).sin() * y * y + x;
+        let exact = (1.0 - 3f64.cos()) / 9.0 + 0.5;
+        let pixels = 64;
+        let total: f64 = (0..pixels)
+            .map(|p| {
+                let estimate: f64 = (0..samples as u64)
+                    .map(|sample| {
+                        let mut sampler = kind.create(7, (p, 0), sample, samples);
+                        for _ in 0..skip {
+                            let value = sampler.get_1d();
+                            assert!((0.0..1.0).contains(&value));
+                        }
+                        let (x, y) = sampler.get_2d();
+                        assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+                        f(x, y)
+                    })
+                    .sum::<f64>()
+                    / samples as f64;
+                (estimate - exact).powi(2)
+            })
+            .sum();
+        total / pixels as f64
+    }
+
+    #[test]
+    fn low_discrepancy_samplers_reduce_error() {
+        for skip in [0, 5] {
+            let independent = mean_squared_error(SamplerKind::Independent, skip, 64);
+            for kind in &KINDS[1..] {
+                let error = mean_squared_error(*kind, skip, 64);
+                assert!(
+                    error * 4.0 < independent,
+                    "{:?} after {} dimensions: {} vs {}",
+                    kind,
+                    skip,
+                    error,
+                    independent
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn dimensions_are_uncorrelated() {
+        // Consecutive pairs of one sample must not repeat the same point.
+        for kind in KINDS {
+            let n = 256;
+            let covariance: f64 = (0..n as u64)
+                .map(|sample| {
+                    let mut sampler = kind.create(3, (1, 2), sample, n);
+                    let (a, _) = sampler.get_2d();
+                    let (b, _) = sampler.get_2d();
+                    (a - 0.5) * (b - 0.5)
+                })
+                .sum::<f64>()
+                / n as f64;
+            assert!(covariance.abs() < 0.02, "{:?}: {}", kind, covariance);
+        }
+    }
+}
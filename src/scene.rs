@@ -0,0 +1,1191 @@
+use crate::color::Color;
+use crate::environment::{Environment, EnvironmentMap};
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::image;
+use crate::material::{
+    Conductor, Dielectric, DiffuseLight, Ior, Lambertian, LinearPolarizer, Material, Metal,
+    RoughDielectric, ThinFilm, D_LINE,
+};
+use crate::obj;
+use crate::perlin::Perlin;
+use crate::sampler::SamplerKind;
+use crate::settings::{CameraSettings, RenderSettings};
+use crate::sphere::Sphere;
+use crate::texture::{
+    CheckerTexture, ImageTexture, NoiseKind, NoiseTexture, SolidColor, Texture, WrapMode,
+};
+use crate::triangle::TriangleMesh;
+use crate::vec3::Vec3;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use toml::Spanned;
+
+/// A world to render together with the camera and render settings it was authored for.
+pub struct Scene {
+    pub world: HittableList,
+    /// Emissive objects of `world` again, for sampling light sources directly.
+    pub lights: HittableList,
+    pub camera: CameraSettings,
+    pub settings: RenderSettings,
+    pub background: Environment,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            SceneError::Parse {
+                path,
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}:{}: {}", path.display(), line, column, message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+pub fn load(path: &Path) -> Result<Scene, SceneError> {
+    let source = fs::read_to_string(path).map_err(|error| SceneError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    parse(&source, path)
+}
+
+/// Parses a TOML scene description. Relative paths in it are resolved against the directory
+/// of `path`.
+pub fn parse(source: &str, path: &Path) -> Result<Scene, SceneError> {
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let error_at = |span: Option<Range<usize>>, message: &str| {
+        let (line, column) = line_column(source, span.map_or(0, |span| span.start));
+        SceneError::Parse {
+            path: path.to_path_buf(),
+            line,
+            column,
+            message: message.trim_end().to_string(),
+        }
+    };
+
+    let file: SceneFile = toml::from_str(source).map_err(|e| error_at(e.span(), e.message()))?;
+
+    let textures = Textures {
+        descs: file.textures,
+        built: BTreeMap::new(),
+        base_dir,
+    };
+    let names: Vec<String> = file.materials.keys().cloned().collect();
+    let mut materials = Materials {
+        descs: file.materials,
+        built: BTreeMap::new(),
+        textures,
+    };
+    for name in names {
+        materials
+            .get(&name)
+            .map_err(|(at, message)| error_at(at, &message))?;
+    }
+    let materials = materials.built;
+
+    let mut objects = Objects::default();
+    for object in file.objects {
+        let span = object.span();
+        let object = object.into_inner();
+        let material = match object.material() {
+            Some(name) => Arc::clone(materials.get(name).ok_or_else(|| {
+                let message = format!("unknown material `{}`", name);
+                error_at(Some(span.clone()), &message)
+            })?),
+            None => Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))),
+        };
+        object
+            .build(material, base_dir, &mut objects)
+            .map_err(|message| error_at(Some(span), &message))?;
+    }
+
+    let background = match file.background {
+        Some(desc) => {
+            let span = desc.span();
+            desc.into_inner()
+                .build(base_dir)
+                .map_err(|message| error_at(Some(span), &message))?
+        }
+        None => Environment::default(),
+    };
+
+    Ok(Scene {
+        world: HittableList {
+            objects: objects.world,
+        },
+        lights: HittableList {
+            objects: objects.lights,
+        },
+        camera: file.camera.build(),
+        settings: file.render.build(),
+        background,
+    })
+}
+
+// 1-based line and column of a byte offset.
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+fn vec3([x, y, z]: [f64; 3]) -> Vec3 {
+    Vec3::new(x, y, z)
+}
+
+fn color([r, g, b]: [f64; 3]) -> Color {
+    Color::new(r, g, b)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    #[serde(default)]
+    render: RenderDesc,
+    #[serde(default)]
+    camera: CameraDesc,
+    background: Option<Spanned<BackgroundDesc>>,
+    #[serde(default)]
+    textures: BTreeMap<String, Spanned<TextureDesc>>,
+    #[serde(default)]
+    materials: BTreeMap<String, Spanned<MaterialDesc>>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectDesc>>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RenderDesc {
+    width: Option<NonZeroU32>,
+    height: Option<NonZeroU32>,
+    #[serde(default, deserialize_with = "positive")]
+    aspect_ratio: Option<f64>,
+    samples_per_pixel: Option<NonZeroUsize>,
+    min_samples_per_pixel: Option<NonZeroUsize>,
+    #[serde(default, deserialize_with = "non_negative")]
+    noise_threshold: Option<f64>,
+    max_depth: Option<NonZeroUsize>,
+    seed: Option<u64>,
+    sampler: Option<SamplerDesc>,
+    spectral: Option<bool>,
+    polarized: Option<bool>,
+}
+
+#[derive(Deserialize)]
+enum SamplerDesc {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl RenderDesc {
+    fn build(&self) -> RenderSettings {
+        let mut settings = RenderSettings::default();
+        let aspect_ratio = self.aspect_ratio.unwrap_or_else(|| settings.aspect_ratio());
+        if let Some(width) = self.width {
+            settings.image_width = width.get();
+        }
+        settings.image_height = match self.height {
+            Some(height) => height.get(),
+            None => ((settings.image_width as f64 / aspect_ratio) as u32).max(1),
+        };
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            settings.samples_per_pixel = samples_per_pixel.get();
+        }
+        if let Some(min_samples) = self.min_samples_per_pixel {
+            settings.min_samples_per_pixel = min_samples.get();
+        }
+        if let Some(noise_threshold) = self.noise_threshold {
+            settings.noise_threshold = noise_threshold;
+        }
+        if let Some(max_depth) = self.max_depth {
+            settings.max_depth = max_depth.get();
+        }
+        if let Some(seed) = self.seed {
+            settings.seed = seed;
+        }
+        if let Some(spectral) = self.spectral {
+            settings.spectral = spectral;
+        }
+        if let Some(polarized) = self.polarized {
+            settings.polarized = polarized;
+        }
+        if let Some(sampler) = &self.sampler {
+            settings.sampler = match sampler {
+                SamplerDesc::Independent => SamplerKind::Independent,
+                SamplerDesc::Stratified => SamplerKind::Stratified,
+                SamplerDesc::Halton => SamplerKind::Halton,
+                SamplerDesc::Sobol => SamplerKind::Sobol,
+                SamplerDesc::BlueNoise => SamplerKind::BlueNoise,
+            };
+        }
+        settings
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    look_from: Option<[f64; 3]>,
+    look_at: Option<[f64; 3]>,
+    vup: Option<[f64; 3]>,
+    #[serde(default, deserialize_with = "field_of_view")]
+    vfov: Option<f64>,
+    #[serde(default, deserialize_with = "non_negative")]
+    aperture: Option<f64>,
+    #[serde(default, deserialize_with = "blade_count")]
+    aperture_blades: Option<u32>,
+    aperture_rotation: Option<f64>,
+    #[serde(default, deserialize_with = "positive")]
+    focus_dist: Option<f64>,
+}
+
+impl CameraDesc {
+    fn build(&self) -> CameraSettings {
+        let defaults = CameraSettings::default();
+        CameraSettings {
+            look_from: self.look_from.map_or(defaults.look_from, vec3),
+            look_at: self.look_at.map_or(defaults.look_at, vec3),
+            vup: self.vup.map_or(defaults.vup, vec3),
+            vfov: self.vfov.unwrap_or(defaults.vfov),
+            aperture: self.aperture.unwrap_or(defaults.aperture),
+            aperture_blades: self.aperture_blades.unwrap_or(defaults.aperture_blades),
+            aperture_rotation: self.aperture_rotation.unwrap_or(defaults.aperture_rotation),
+            focus_dist: self.focus_dist.unwrap_or(defaults.focus_dist),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+enum BackgroundDesc {
+    Solid {
+        color: [f64; 3],
+    },
+    Gradient {
+        bottom: [f64; 3],
+        top: [f64; 3],
+    },
+    /// Equirectangular environment map, `rotation` in degrees about the vertical axis.
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default, deserialize_with = "non_negative")]
+        intensity: Option<f64>,
+    },
+}
+
+impl BackgroundDesc {
+    fn build(self, base_dir: &Path) -> Result<Environment, String> {
+        Ok(match self {
+            BackgroundDesc::Solid { color: c } => Environment::Solid(color(c)),
+            BackgroundDesc::Gradient { bottom, top } => Environment::Gradient {
+                bottom: color(bottom),
+                top: color(top),
+            },
+            BackgroundDesc::Image {
+                path,
+                rotation,
+                intensity,
+            } => {
+                let path = base_dir.join(path);
+                let map = EnvironmentMap::load(&path, rotation, intensity.unwrap_or(1.0))
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+                Environment::Map(Arc::new(map))
+            }
+        })
+    }
+}
+
+// Error message with the position of the table it is about, when known.
+type Located = (Option<Range<usize>>, String);
+
+/// A color given inline as `[r, g, b]` or the name of a texture.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureRef {
+    Color([f64; 3]),
+    Named(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+enum TextureDesc {
+    Solid {
+        color: [f64; 3],
+    },
+    Checker {
+        even: TextureRef,
+        odd: TextureRef,
+        #[serde(default = "one")]
+        scale: f64,
+    },
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        wrap: WrapDesc,
+    },
+    Noise {
+        #[serde(default)]
+        kind: NoiseDesc,
+        #[serde(default = "one")]
+        scale: f64,
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+#[derive(Default, Deserialize)]
+enum WrapDesc {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+#[derive(Default, Deserialize)]
+enum NoiseDesc {
+    #[default]
+    Noise,
+    Turbulence,
+    Marble,
+}
+
+/// Named textures, built on first use so that they can refer to each other.
+struct Textures<'a> {
+    descs: BTreeMap<String, Spanned<TextureDesc>>,
+    built: BTreeMap<String, Arc<dyn Texture>>,
+    base_dir: &'a Path,
+}
+
+impl Textures<'_> {
+    fn get(&mut self, texture: TextureRef) -> Result<Arc<dyn Texture>, Located> {
+        let name = match texture {
+            TextureRef::Color(c) => return Ok(Arc::new(SolidColor { color: color(c) })),
+            TextureRef::Named(name) => name,
+        };
+        if let Some(texture) = self.built.get(&name) {
+            return Ok(Arc::clone(texture));
+        }
+        // Textures being built have left `descs` but are not in `built` yet.
+        let desc = self
+            .descs
+            .remove(&name)
+            .ok_or_else(|| (None, format!("unknown or cyclic texture `{}`", name)))?;
+        let span = desc.span();
+        let texture = self
+            .build(desc.into_inner())
+            .map_err(|(at, message)| (Some(at.unwrap_or(span)), message))?;
+        self.built.insert(name, Arc::clone(&texture));
+        Ok(texture)
+    }
+
+    fn build(&mut self, desc: TextureDesc) -> Result<Arc<dyn Texture>, Located> {
+        Ok(match desc {
+            TextureDesc::Solid { color: c } => Arc::new(SolidColor { color: color(c) }),
+            TextureDesc::Checker { even, odd, scale } => {
+                if !scale.is_finite() || scale <= 0.0 {
+                    return Err((None, "checker scale must be greater than 0".to_string()));
+                }
+                Arc::new(CheckerTexture {
+                    even: self.get(even)?,
+                    odd: self.get(odd)?,
+                    scale,
+                })
+            }
+            TextureDesc::Image { path, wrap } => {
+                let path = self.base_dir.join(path);
+                let image = image::read_image(&path)
+                    .map_err(|e| (None, format!("{}: {}", path.display(), e)))?;
+                let wrap = match wrap {
+                    WrapDesc::Repeat => WrapMode::Repeat,
+                    WrapDesc::Clamp => WrapMode::Clamp,
+                    WrapDesc::Mirror => WrapMode::Mirror,
+                };
+                Arc::new(ImageTexture { image, wrap })
+            }
+            TextureDesc::Noise { kind, scale, seed } => Arc::new(NoiseTexture {
+                noise: Perlin::new(&mut StdRng::seed_from_u64(seed)),
+                scale,
+                kind: match kind {
+                    NoiseDesc::Noise => NoiseKind::Noise,
+                    NoiseDesc::Turbulence => NoiseKind::Turbulence,
+                    NoiseDesc::Marble => NoiseKind::Marble,
+                },
+            }),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: TextureRef,
+    },
+    Metal {
+        albedo: TextureRef,
+        #[serde(default)]
+        fuzz: f64,
+    },
+    /// Microfacet metal with the complex index of refraction of a named `metal` or `eta + ik`.
+    Conductor {
+        metal: Option<MetalDesc>,
+        eta: Option<[f64; 3]>,
+        k: Option<[f64; 3]>,
+        #[serde(default)]
+        roughness: f64,
+    },
+    /// Glass with a constant index `ir`, or one that varies with the wavelength as given by an
+    /// Abbe number along with `ir`, `cauchy = [a, b]` or Sellmeier coefficients.
+    Dielectric {
+        ir: Option<f64>,
+        #[serde(default, deserialize_with = "positive")]
+        abbe: Option<f64>,
+        cauchy: Option<[f64; 2]>,
+        sellmeier: Option<SellmeierDesc>,
+    },
+    RoughDielectric {
+        ir: f64,
+        #[serde(default)]
+        roughness: f64,
+    },
+    DiffuseLight {
+        emit: [f64; 3],
+    },
+    /// Film `thickness` nanometers thick over the material named `base`.
+    ThinFilm {
+        base: String,
+        thickness: f64,
+        ior: f64,
+        #[serde(default = "one")]
+        base_ior: f64,
+    },
+    /// Filter passing light polarized along `axis`.
+    LinearPolarizer {
+        axis: [f64; 3],
+    },
+}
+
+struct Materials<'a> {
+    descs: BTreeMap<String, Spanned<MaterialDesc>>,
+    built: BTreeMap<String, Arc<dyn Material>>,
+    textures: Textures<'a>,
+}
+
+impl Materials<'_> {
+    fn get(&mut self, name: &str) -> Result<Arc<dyn Material>, Located> {
+        if let Some(material) = self.built.get(name) {
+            return Ok(Arc::clone(material));
+        }
+        // Materials being built have left `descs` but are not in `built` yet.
+        let desc = self
+            .descs
+            .remove(name)
+            .ok_or_else(|| (None, format!("unknown or cyclic material `{}`", name)))?;
+        let span = desc.span();
+        let material = desc
+            .into_inner()
+            .build(self)
+            .map_err(|(at, message)| (Some(at.unwrap_or(span)), message))?;
+        self.built.insert(name.to_string(), Arc::clone(&material));
+        Ok(material)
+    }
+}
+
+#[derive(Deserialize)]
+enum MetalDesc {
+    Gold,
+    Silver,
+    Copper,
+    Aluminium,
+}
+
+impl MetalDesc {
+    // Complex index of refraction `(eta, k)` at the red, green and blue channels.
+    fn ior(&self) -> ([f64; 3], [f64; 3]) {
+        match self {
+            MetalDesc::Gold => ([0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+            MetalDesc::Silver => ([0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+            MetalDesc::Copper => ([0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+            MetalDesc::Aluminium => ([1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+        }
+    }
+}
+
+fn check_roughness(roughness: f64) -> Result<(), Located> {
+    if (0.0..=1.0).contains(&roughness) {
+        Ok(())
+    } else {
+        Err((None, "roughness must be between 0 and 1".to_string()))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SellmeierDesc {
+    b: [f64; 3],
+    c: [f64; 3],
+}
+
+impl MaterialDesc {
+    fn build(self, materials: &mut Materials) -> Result<Arc<dyn Material>, Located> {
+        let textures = &mut materials.textures;
+        Ok(match self {
+            MaterialDesc::Lambertian { albedo } => {
+                Arc::new(Lambertian::textured(textures.get(albedo)?))
+            }
+            MaterialDesc::Metal { albedo, fuzz } => {
+                Arc::new(Metal::textured(textures.get(albedo)?, fuzz))
+            }
+            MaterialDesc::Conductor {
+                metal,
+                eta,
+                k,
+                roughness,
+            } => {
+                check_roughness(roughness)?;
+                let (eta, k) = match (metal, eta, k) {
+                    (Some(metal), None, None) => metal.ior(),
+                    (None, Some(eta), Some(k)) => (eta, k),
+                    _ => {
+                        return Err((
+                            None,
+                            "a conductor needs either a `metal` or both `eta` and `k`".to_string(),
+                        ))
+                    }
+                };
+                if eta.iter().any(|&n| n.is_nan() || n <= 0.0)
+                    || k.iter().any(|&k| k.is_nan() || k < 0.0)
+                {
+                    return Err((
+                        None,
+                        "`eta` must be greater than 0 and `k` non-negative".to_string(),
+                    ));
+                }
+                Arc::new(Conductor::new(color(eta), color(k), roughness))
+            }
+            MaterialDesc::Dielectric {
+                ir,
+                abbe,
+                cauchy,
+                sellmeier,
+            } => {
+                let ior = match (ir, abbe, cauchy, sellmeier) {
+                    (Some(ir), None, None, None) => Ior::Constant(ir),
+                    (Some(ir), Some(abbe), None, None) => Ior::from_abbe(ir, abbe),
+                    (None, None, Some([a, b]), None) => Ior::Cauchy { a, b },
+                    (None, None, None, Some(SellmeierDesc { b, c })) => Ior::Sellmeier { b, c },
+                    (None, Some(_), _, _) => {
+                        return Err((None, "an Abbe number needs the `ir` it is for".to_string()))
+                    }
+                    _ => {
+                        return Err((
+                            None,
+                            "a dielectric needs exactly one of `ir`, `cauchy` and `sellmeier`"
+                                .to_string(),
+                        ))
+                    }
+                };
+                let n = ior.at(D_LINE);
+                if n.is_nan() || n <= 0.0 {
+                    return Err((
+                        None,
+                        "index of refraction must be greater than 0".to_string(),
+                    ));
+                }
+                Arc::new(Dielectric { ior })
+            }
+            MaterialDesc::RoughDielectric { ir, roughness } => {
+                check_roughness(roughness)?;
+                if ir.is_nan() || ir <= 0.0 {
+                    return Err((
+                        None,
+                        "index of refraction must be greater than 0".to_string(),
+                    ));
+                }
+                Arc::new(RoughDielectric::new(ir, roughness))
+            }
+            MaterialDesc::DiffuseLight { emit } => Arc::new(DiffuseLight { emit: color(emit) }),
+            MaterialDesc::ThinFilm {
+                base,
+                thickness,
+                ior,
+                base_ior,
+            } => {
+                if !(thickness >= 0.0 && ior > 0.0 && base_ior > 0.0) {
+                    return Err((
+                        None,
+                        "thin film thickness must be non-negative and indices of refraction \
+                         greater than 0"
+                            .to_string(),
+                    ));
+                }
+                Arc::new(ThinFilm {
+                    base: materials.get(&base)?,
+                    thickness,
+                    ior,
+                    base_ior,
+                })
+            }
+            MaterialDesc::LinearPolarizer { axis } => {
+                let axis = vec3(axis);
+                if axis.near_zero() {
+                    return Err((None, "polarizer axis must not be zero".to_string()));
+                }
+                Arc::new(LinearPolarizer { axis })
+            }
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+enum ObjectDesc {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        normals: Option<[[f64; 3]; 3]>,
+        material: String,
+    },
+    /// Parallelogram with corner `q` and edges `u` and `v`, facing along `u × v`.
+    Quad {
+        q: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+    },
+    Mesh {
+        positions: Vec<[f64; 3]>,
+        #[serde(default)]
+        normals: Vec<[f64; 3]>,
+        #[serde(default)]
+        uvs: Vec<[f64; 2]>,
+        indices: Vec<[usize; 3]>,
+        material: String,
+    },
+    /// Wavefront OBJ model, `material` is used for faces without an MTL material.
+    Obj {
+        path: PathBuf,
+        material: Option<String>,
+        #[serde(default = "one")]
+        scale: f64,
+        #[serde(default)]
+        translate: [f64; 3],
+    },
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+impl ObjectDesc {
+    fn material(&self) -> Option<&str> {
+        match self {
+            ObjectDesc::Sphere { material, .. }
+            | ObjectDesc::Triangle { material, .. }
+            | ObjectDesc::Quad { material, .. }
+            | ObjectDesc::Mesh { material, .. } => Some(material),
+            ObjectDesc::Obj { material, .. } => material.as_deref(),
+        }
+    }
+
+    fn build(
+        self,
+        material: Arc<dyn Material>,
+        base_dir: &Path,
+        objects: &mut Objects,
+    ) -> Result<(), String> {
+        match self {
+            ObjectDesc::Sphere { center, radius, .. } => objects.add_sphere(Sphere {
+                center: vec3(center),
+                radius,
+                material,
+            }),
+            ObjectDesc::Triangle {
+                vertices, normals, ..
+            } => {
+                let mesh = TriangleMesh::new(
+                    vertices.iter().copied().map(vec3).collect(),
+                    normals.iter().flatten().copied().map(vec3).collect(),
+                    Vec::new(),
+                    vec![[0, 1, 2]],
+                    material,
+                )?;
+                objects.add_mesh(mesh);
+            }
+            ObjectDesc::Quad { q, u, v, .. } => {
+                let (q, u, v) = (vec3(q), vec3(u), vec3(v));
+                if u.cross(v).near_zero() {
+                    return Err("quad edges must not be parallel".to_string());
+                }
+                let mesh = TriangleMesh::new(
+                    vec![q, q + u, q + u + v, q + v],
+                    Vec::new(),
+                    vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+                    vec![[0, 1, 2], [0, 2, 3]],
+                    material,
+                )?;
+                objects.add_mesh(mesh);
+            }
+            ObjectDesc::Mesh {
+                positions,
+                normals,
+                uvs,
+                indices,
+                ..
+            } => {
+                let mesh = TriangleMesh::new(
+                    positions.into_iter().map(vec3).collect(),
+                    normals.into_iter().map(vec3).collect(),
+                    uvs.into_iter().map(|[u, v]| (u, v)).collect(),
+                    indices,
+                    material,
+                )?;
+                objects.add_mesh(mesh);
+            }
+            ObjectDesc::Obj {
+                path,
+                scale,
+                translate,
+                ..
+            } => {
+                let meshes =
+                    obj::load(&base_dir.join(path), material).map_err(|e| e.to_string())?;
+                for mut mesh in meshes {
+                    for p in &mut mesh.positions {
+                        *p = scale * *p + vec3(translate);
+                    }
+                    objects.add_mesh(mesh);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Objects built from a scene, with the emissive ones also collected as lights.
+#[derive(Default)]
+struct Objects {
+    world: Vec<Box<dyn Hittable>>,
+    lights: Vec<Box<dyn Hittable>>,
+}
+
+impl Objects {
+    fn add_sphere(&mut self, sphere: Sphere) {
+        if sphere.material.is_light() {
+            self.lights.push(Box::new(sphere.clone()));
+        }
+        self.world.push(Box::new(sphere));
+    }
+
+    fn add_mesh(&mut self, mesh: TriangleMesh) {
+        let mesh = Arc::new(mesh);
+        if mesh.material.is_light() {
+            self.lights.extend(Arc::clone(&mesh).triangles());
+        }
+        self.world.extend(mesh.triangles());
+    }
+}
+
+fn checked<'de, D>(
+    deserializer: D,
+    valid: fn(f64) -> bool,
+    expected: &str,
+) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = f64::deserialize(deserializer)?;
+    if value.is_finite() && valid(value) {
+        Ok(Some(value))
+    } else {
+        Err(de::Error::custom(format!(
+            "invalid value {}, expected {}",
+            value, expected
+        )))
+    }
+}
+
+fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    checked(deserializer, |v| v > 0.0, "a number greater than 0")
+}
+
+fn non_negative<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    checked(deserializer, |v| v >= 0.0, "a non-negative number")
+}
+
+fn field_of_view<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    checked(
+        deserializer,
+        |v| v > 0.0 && v < 180.0,
+        "an angle between 0 and 180 degrees",
+    )
+}
+
+fn blade_count<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let value = u32::deserialize(deserializer)?;
+    if value == 0 || value >= 3 {
+        Ok(Some(value))
+    } else {
+        Err(de::Error::custom(format!(
+            "invalid value {}, expected 0 for a round aperture or at least 3 blades",
+            value
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Scatter;
+    use crate::ray::Ray;
+    use crate::sampler::IndependentSampler;
+    use crate::spectrum::Wavelengths;
+    use crate::vec3::Point3;
+    use std::f64::consts::PI;
+
+    const SCENE: &str = r#"
+[render]
+width = 300
+aspect_ratio = 1.5
+samples_per_pixel = 8
+
+[camera]
+look_from = [0, 1, 5]
+vfov = 40
+
+[materials.ground]
+type = "Lambertian"
+albedo = [0.5, 0.5, 0.5]
+
+[materials.glass]
+type = "Dielectric"
+ir = 1.5
+
+[[objects]]
+type = "Sphere"
+center = [0, -1000, 0]
+radius = 1000
+material = "ground"
+
+[[objects]]
+type = "Sphere"
+center = [0, 1, 0]
+radius = 1
+material = "glass"
+"#;
+
+    fn parse_error(source: &str) -> (usize, usize, String) {
+        match parse(source, Path::new("test.toml")) {
+            Err(SceneError::Parse {
+                line,
+                column,
+                message,
+                ..
+            }) => (line, column, message),
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("scene should not parse"),
+        }
+    }
+
+    #[test]
+    fn parses_scene() {
+        let scene = parse(SCENE, Path::new("test.toml")).unwrap();
+        assert_eq!(scene.world.objects.len(), 2);
+        assert_eq!(scene.settings.image_width, 300);
+        assert_eq!(scene.settings.image_height, 200);
+        assert_eq!(scene.settings.samples_per_pixel, 8);
+        assert_eq!(scene.camera.look_from, Vec3::new(0.0, 1.0, 5.0));
+        assert_eq!(scene.camera.vfov, 40.0);
+        assert_eq!(scene.camera.aperture, CameraSettings::default().aperture);
+    }
+
+    #[test]
+    fn reports_unknown_material_position() {
+        let source = SCENE.replace("material = \"glass\"", "material = \"gold\"");
+        let (line, column, message) = parse_error(&source);
+        assert_eq!((line, column), (25, 1));
+        assert_eq!(message, "unknown material `gold`");
+    }
+
+    #[test]
+    fn reports_invalid_value_position() {
+        let source = SCENE.replace("vfov = 40", "vfov = 400");
+        let (line, column, _) = parse_error(&source);
+        assert_eq!((line, column), (9, 8));
+    }
+
+    #[test]
+    fn reports_missing_environment_map() {
+        let source = format!(
+            "{}\n[background]\ntype = \"Image\"\npath = \"missing.hdr\"\nrotation = 90\n",
+            SCENE
+        );
+        let (_, _, message) = parse_error(&source);
+        assert!(message.starts_with("missing.hdr: "), "{}", message);
+        let (line, _, message) = parse_error(&source.replace("rotation = 90", "intensity = -1"));
+        assert_eq!(line, 31);
+        assert!(message.contains("non-negative"), "{}", message);
+    }
+
+    #[test]
+    fn dispersive_dielectrics() {
+        let scene = load(Path::new("scenes/prism.toml")).unwrap();
+        assert!(scene.settings.spectral);
+        let source = SCENE.replace("ir = 1.5", "abbe = 30");
+        let (_, _, message) = parse_error(&source);
+        assert_eq!(message, "an Abbe number needs the `ir` it is for");
+        let source = SCENE.replace("ir = 1.5", "ir = 1.5\ncauchy = [1.5, 0.004]");
+        let (_, _, message) = parse_error(&source);
+        assert!(message.contains("exactly one of"), "{}", message);
+    }
+
+    #[test]
+    fn prism_spreads_light_into_a_spectrum() {
+        let scene = load(Path::new("scenes/prism.toml")).unwrap();
+        // Elevation of the light that leaves the prism after refracting into and out of it, for
+        // a ray that crosses it parallel to its base at the angle of minimum deviation.
+        let exit_elevation = |u: f64| {
+            let incidence = (1.62_f64 * 0.5).asin();
+            let direction = Vec3::new(
+                0.0,
+                (incidence - PI / 6.0).sin(),
+                (incidence - PI / 6.0).cos(),
+            );
+            // Aimed at the front face 0.4 up, where it is 0.6 * (1 - 0.4 / 1.0392) from the middle.
+            let mut r = Ray::new(Point3::new(0.0, 0.4, -0.369) - 2.0 * direction, direction);
+            r.wavelengths = Some(Wavelengths::sample(u));
+            // Retry until neither face reflects the path.
+            (0..)
+                .find_map(|sample| {
+                    let mut sampler = IndependentSampler::new(0, (0, 0), sample);
+                    let mut ray = r;
+                    for _ in 0..2 {
+                        let rec = scene.world.hit(&ray, 0.001, f64::INFINITY)?;
+                        match rec.material.scatter(&ray, &rec, &mut sampler)? {
+                            Scatter::Specular { scattered, .. } if scattered.direction.z > 0.0 => {
+                                ray = scattered
+                            }
+                            _ => return None,
+                        }
+                    }
+                    let direction = ray.direction.unit_vector();
+                    Some(direction.y.atan2(direction.z))
+                })
+                .unwrap()
+        };
+        // Short wavelengths are bent down further than long ones
+        let (blue, red) = (exit_elevation(0.15), exit_elevation(0.7));
+        assert!(blue < -0.3 && red < -0.3, "{} {}", blue, red);
+        assert!(red - blue > 0.03, "{} {}", blue, red);
+    }
+
+    #[test]
+    fn thin_films_refer_to_their_base() {
+        let film = |base: &str| {
+            format!(
+                "{}\n[materials.bubble]\ntype = \"ThinFilm\"\nbase = \"{}\"\nthickness = 380\n\
+                 ior = 1.33\n",
+                SCENE, base
+            )
+        };
+        // Materials may name ones defined after them
+        assert!(parse(&film("glass"), Path::new("test.toml")).is_ok());
+        let (line, _, message) = parse_error(&film("bubble"));
+        assert_eq!(line, 31);
+        assert_eq!(message, "unknown or cyclic material `bubble`");
+    }
+
+    #[test]
+    fn conductors_take_a_metal_or_an_index() {
+        let conductor = |fields: &str| {
+            format!(
+                "{}\n[materials.shiny]\ntype = \"Conductor\"\n{}\n",
+                SCENE, fields
+            )
+        };
+        let valid = [
+            "metal = \"Gold\"\nroughness = 0.3",
+            "eta = [0.2, 0.9, 1.1]\nk = [3.9, 2.5, 2.1]",
+        ];
+        for fields in valid {
+            assert!(parse(&conductor(fields), Path::new("test.toml")).is_ok());
+        }
+        let (_, _, message) = parse_error(&conductor("metal = \"Gold\"\nk = [1, 1, 1]"));
+        assert_eq!(
+            message,
+            "a conductor needs either a `metal` or both `eta` and `k`"
+        );
+        let (_, _, message) = parse_error(&conductor("metal = \"Silver\"\nroughness = 2"));
+        assert_eq!(message, "roughness must be between 0 and 1");
+    }
+
+    #[test]
+    fn builds_triangles_and_meshes() {
+        let source = format!(
+            "{}{}",
+            SCENE,
+            r#"
+[[objects]]
+type = "Triangle"
+vertices = [[0, 0, 0], [1, 0, 0], [0, 1, 0]]
+material = "ground"
+
+[[objects]]
+type = "Mesh"
+positions = [[0, 0, 0], [1, 0, 0], [1, 1, 0], [0, 1, 0]]
+indices = [[0, 1, 2], [0, 2, 3]]
+material = "ground"
+"#
+        );
+        let scene = parse(&source, Path::new("test.toml")).unwrap();
+        assert_eq!(scene.world.objects.len(), 5);
+
+        let source = source.replace("[0, 2, 3]", "[0, 2, 4]");
+        let (line, column, message) = parse_error(&source);
+        assert_eq!((line, column), (36, 1));
+        assert_eq!(message, "vertex index 4 is out of range for 4 vertices");
+    }
+
+    #[test]
+    fn builds_lights_quads_and_background() {
+        let source = format!(
+            "{}{}",
+            SCENE,
+            r#"
+[background]
+type = "Solid"
+color = [0, 0, 0]
+
+[materials.lamp]
+type = "DiffuseLight"
+emit = [4, 4, 4]
+
+[[objects]]
+type = "Quad"
+q = [5, 2, 0]
+u = [1, 0, 0]
+v = [0, 0, 1]
+material = "lamp"
+"#
+        );
+        let scene = parse(&source, Path::new("test.toml")).unwrap();
+        assert_eq!(scene.world.objects.len(), 4);
+        assert_eq!(scene.lights.objects.len(), 2);
+        assert_eq!(
+            scene.background,
+            Environment::Solid(Color::new(0.0, 0.0, 0.0))
+        );
+
+        let r = Ray::new(Point3::new(5.5, 0.0, 0.5), Vec3::new(0.0, 1.0, 0.0));
+        let rec = scene.world.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(rec.t, 2.0);
+        assert_eq!(rec.material.emitted(&r, &rec), Color::new(4.0, 4.0, 4.0));
+
+        let source = source.replace("v = [0, 0, 1]", "v = [2, 0, 0]");
+        let (_, _, message) = parse_error(&source);
+        assert_eq!(message, "quad edges must not be parallel");
+    }
+
+    #[test]
+    fn builds_textures() {
+        let source = r#"
+[textures.white]
+type = "Solid"
+color = [0.9, 0.9, 0.9]
+
+[textures.checker]
+type = "Checker"
+even = "white"
+odd = [0.2, 0.3, 0.1]
+scale = 0.5
+
+[textures.marble]
+type = "Noise"
+kind = "Marble"
+scale = 4
+
+[materials.ground]
+type = "Lambertian"
+albedo = "checker"
+
+[materials.stone]
+type = "Metal"
+albedo = "marble"
+fuzz = 0.5
+
+[materials.plain]
+type = "Lambertian"
+albedo = [0.5, 0.5, 0.5]
+
+[[objects]]
+type = "Sphere"
+center = [0, 0, 0]
+radius = 1
+material = "ground"
+"#;
+        let scene = parse(source, Path::new("test.toml")).unwrap();
+        let r = Ray::new(Point3::new(0.25, 0.25, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = scene.world.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!(rec.u > 0.0 && rec.v > 0.5);
+
+        let (line, column, message) =
+            parse_error(&source.replace("odd = [0.2, 0.3, 0.1]", "odd = \"checker\""));
+        assert_eq!((line, column), (6, 1));
+        assert_eq!(message, "unknown or cyclic texture `checker`");
+
+        let (line, _, message) =
+            parse_error(&source.replace("albedo = \"marble\"", "albedo = \"wood\""));
+        assert_eq!(line, 21);
+        assert_eq!(message, "unknown or cyclic texture `wood`");
+
+        let (_, _, message) = parse_error(&source.replace("kind = \"Marble\"", "kind = \"Wood\""));
+        assert!(message.contains("Wood"), "{}", message);
+    }
+
+    #[test]
+    fn reports_unknown_material_type() {
+        let (line, _, message) = parse_error("[materials.x]\ntype = \"Plastic\"\n");
+        assert_eq!(line, 2);
+        assert!(message.contains("Plastic"), "{}", message);
+    }
+}
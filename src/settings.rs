@@ -0,0 +1,103 @@
+use crate::camera::Camera;
+use crate::sampler::SamplerKind;
+use crate::vec3::{Point3, Vec3};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RenderSettings {
+    pub image_width: u32,
+    pub image_height: u32,
+    /// Upper bound of the samples per pixel.
+    pub samples_per_pixel: usize,
+    /// Samples every pixel takes before it may stop at the noise threshold.
+    pub min_samples_per_pixel: usize,
+    /// Relative standard error at which a pixel stops sampling, 0 to always take
+    /// `samples_per_pixel`.
+    pub noise_threshold: f64,
+    pub max_depth: usize,
+    /// Seeds every random decision of the render.
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    /// Trace wavelengths instead of RGB, see [`crate::spectrum`].
+    pub spectral: bool,
+    /// Trace the polarization of light, see [`crate::polarization`].
+    pub polarized: bool,
+}
+
+impl RenderSettings {
+    pub fn aspect_ratio(&self) -> f64 {
+        self.image_width as f64 / self.image_height as f64
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            image_width: 1200,
+            image_height: 800,
+            samples_per_pixel: 500,
+            min_samples_per_pixel: 16,
+            noise_threshold: 0.0,
+            max_depth: 50,
+            seed: 0,
+            sampler: SamplerKind::Sobol,
+            spectral: false,
+            polarized: false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraSettings {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vup: Vec3,
+    pub vfov: f64, // vertical field-of-view in degrees
+    pub aperture: f64,
+    /// Number of straight aperture edges, 0 for a round aperture.
+    pub aperture_blades: u32,
+    /// Angle of the first aperture corner in degrees.
+    pub aperture_rotation: f64,
+    pub focus_dist: f64,
+}
+
+impl CameraSettings {
+    /// Camera for an image of the given width to height ratio.
+    pub fn build(&self, aspect_ratio: f64) -> Camera {
+        let CameraSettings {
+            look_from,
+            look_at,
+            vup,
+            vfov,
+            aperture,
+            aperture_blades,
+            aperture_rotation,
+            focus_dist,
+        } = *self;
+
+        Camera::new(
+            look_from,
+            look_at,
+            vup,
+            vfov,
+            aspect_ratio,
+            aperture,
+            focus_dist,
+        )
+        .with_blades(aperture_blades, aperture_rotation)
+    }
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            look_from: Point3::new(13.0, 2.0, 3.0),
+            look_at: Point3::new(0.0, 0.0, 0.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 20.0,
+            aperture: 0.1,
+            aperture_blades: 0,
+            aperture_rotation: 0.0,
+            focus_dist: 10.0,
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Spectral rendering with hero wavelength sampling, Wilkie et al. 2014. Every camera path
+//! carries radiance at three wavelengths spread evenly over the visible range, stored in the
+//! channels of a [`Color`]: the hero wavelength in `r`, and the two rotations of it by a third of
+//! the range in `g` and `b`. RGB colors of the scene are upsampled to spectra where the path
+//! meets them, and the radiance is projected onto the CIE color matching functions at the film.
+
+use crate::color::Color;
+
+/// Shortest wavelength sampled, in nanometers.
+pub const MIN_WAVELENGTH: f64 = 380.0;
+/// Longest wavelength sampled, in nanometers.
+pub const MAX_WAVELENGTH: f64 = 780.0;
+const RANGE: f64 = MAX_WAVELENGTH - MIN_WAVELENGTH;
+
+/// The wavelengths a path is traced at.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Wavelengths {
+    pub lambda: [f64; 3],
+    // Wavelengths still carried, 1 once the path has kept only the hero.
+    count: usize,
+}
+
+impl Wavelengths {
+    /// The hero wavelength at `u` in [0, 1) across the visible range, and its two companions.
+    pub fn sample(u: f64) -> Self {
+        let lambda =
+            [0.0, 1.0, 2.0].map(|i| MIN_WAVELENGTH + (u + i / 3.0).rem_euclid(1.0) * RANGE);
+        Self { lambda, count: 3 }
+    }
+
+    /// The hero wavelength, the one that picks directions which depend on the wavelength.
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// Drops the companion wavelengths, for events like dispersion that would send each of them
+    /// its own way. Returns the weight that keeps the estimate of the path unbiased.
+    pub fn hero_only(&mut self) -> Color {
+        let weight = Color::new(self.count as f64, 0.0, 0.0);
+        self.count = 1;
+        weight
+    }
+
+    /// Spectral values of a linear sRGB reflectance or emission at the wavelengths.
+    pub fn upsample(&self, rgb: Color) -> Color {
+        let [a, b, c] = self.lambda.map(|lambda| rgb_to_spectrum(rgb, lambda));
+        Color::new(a, b, c)
+    }
+
+    /// Linear sRGB estimate of the radiance `values` measured at the wavelengths, white balanced
+    /// so that the spectrum of an RGB white converts back to white.
+    pub fn to_rgb(&self, values: Color) -> Color {
+        let mut xyz = [0.0; 3];
+        for (&lambda, value) in self.lambda.iter().zip([values.r, values.g, values.b]) {
+            let (x, y, z) = cie_xyz(lambda);
+            xyz[0] += value * x;
+            xyz[1] += value * y;
+            xyz[2] += value * z;
+        }
+        // Uniform density 1 / RANGE, averaged over three wavelengths.
+        let [x, y, z] = xyz.map(|v| v * RANGE / (3.0 * CIE_Y_INTEGRAL));
+        let rgb = xyz_to_srgb(x, y, z);
+        Color::new(
+            rgb.r / EQUAL_ENERGY_RGB[0],
+            rgb.g / EQUAL_ENERGY_RGB[1],
+            rgb.b / EQUAL_ENERGY_RGB[2],
+        )
+    }
+}
+
+// Integral of the CIE ȳ fit below over the sampled range, normalizing Y of a constant spectrum to
+// its value.
+const CIE_Y_INTEGRAL: f64 = 106.919735;
+
+// Linear sRGB of the equal energy spectrum, which the D65 white of sRGB sees as pinkish.
+const EQUAL_ENERGY_RGB: [f64; 3] = [1.2005363, 0.9496664, 0.9078287];
+
+/// CIE 1931 2° color matching functions, the multi-lobe Gaussian fit of Wyman, Sloan and Shirley
+/// 2013.
+pub fn cie_xyz(lambda: f64) -> (f64, f64, f64) {
+    let g = |mu: f64, below: f64, above: f64| {
+        let sigma = if lambda < mu { below } else { above };
+        (-0.5 * ((lambda - mu) / sigma).powi(2)).exp()
+    };
+    (
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+/// CIE XYZ to linear sRGB with its D65 white point.
+pub fn xyz_to_srgb(x: f64, y: f64, z: f64) -> Color {
+    Color::new(
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    )
+}
+
+// Smits 1999 basis spectra over ten equal bins from 380 to 720 nm.
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+/// Smooth spectrum of a linear sRGB color at `lambda` nanometers, Smits' "An RGB-to-spectrum
+/// conversion for reflectances": white plus the one secondary and one primary that make up the
+/// rest. Scales with the color, so it upsamples emission as well as reflectance.
+pub fn rgb_to_spectrum(rgb: Color, lambda: f64) -> f64 {
+    let bin = (((lambda - 380.0) / 34.0).max(0.0) as usize).min(9);
+    let [r, g, b] = [rgb.r, rgb.g, rgb.b].map(|c| c.max(0.0));
+    let basis = |spectrum: &[f64; 10]| spectrum[bin];
+    if r <= g && r <= b {
+        r * basis(&SMITS_WHITE)
+            + if g <= b {
+                (g - r) * basis(&SMITS_CYAN) + (b - g) * basis(&SMITS_BLUE)
+            } else {
+                (b - r) * basis(&SMITS_CYAN) + (g - b) * basis(&SMITS_GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * basis(&SMITS_WHITE)
+            + if r <= b {
+                (r - g) * basis(&SMITS_MAGENTA) + (b - r) * basis(&SMITS_BLUE)
+            } else {
+                (b - g) * basis(&SMITS_MAGENTA) + (r - b) * basis(&SMITS_RED)
+            }
+    } else {
+        b * basis(&SMITS_WHITE)
+            + if r <= g {
+                (r - b) * basis(&SMITS_YELLOW) + (g - r) * basis(&SMITS_GREEN)
+            } else {
+                (g - b) * basis(&SMITS_YELLOW) + (r - g) * basis(&SMITS_RED)
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Color the film sees for a path that found `rgb` at every wavelength, averaged over
+    // stratified hero wavelengths.
+    fn round_trip(rgb: Color) -> Color {
+        let n = 3000;
+        (0..n)
+            .map(|i| {
+                let wavelengths = Wavelengths::sample((i as f64 + 0.5) / n as f64);
+                wavelengths.to_rgb(wavelengths.upsample(rgb))
+            })
+            .fold(Color::new(0.0, 0.0, 0.0), |sum, c| sum + c)
+            * (1.0 / n as f64)
+    }
+
+    #[test]
+    fn constants_match_the_color_matching_functions() {
+        let steps = 100_000;
+        let step = RANGE / steps as f64;
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        for i in 0..steps {
+            let (xi, yi, zi) = cie_xyz(MIN_WAVELENGTH + (i as f64 + 0.5) * step);
+            x += xi * step;
+            y += yi * step;
+            z += zi * step;
+        }
+        assert!((y - CIE_Y_INTEGRAL).abs() < 1e-5, "{}", y);
+        let white = xyz_to_srgb(x / y, 1.0, z / y);
+        for (channel, expected) in [white.r, white.g, white.b].iter().zip(EQUAL_ENERGY_RGB) {
+            assert!((channel - expected).abs() < 1e-6, "{:?}", white);
+        }
+    }
+
+    #[test]
+    fn wavelengths_cover_the_range_evenly() {
+        let wavelengths = Wavelengths::sample(0.9);
+        assert!((wavelengths.hero() - 740.0).abs() < 1e-9);
+        assert!((wavelengths.lambda[1] - (380.0 + 0.2333333333 * RANGE)).abs() < 1e-6);
+        assert!((wavelengths.lambda[2] - (380.0 + 0.5666666667 * RANGE)).abs() < 1e-6);
+        let mut wavelengths = wavelengths;
+        assert_eq!(wavelengths.hero_only(), Color::new(3.0, 0.0, 0.0));
+        assert_eq!(wavelengths.hero_only(), Color::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn upsampled_colors_come_back_close() {
+        for rgb in [
+            Color::new(1.0, 1.0, 1.0),
+            Color::new(0.5, 0.5, 0.5),
+            Color::new(0.8, 0.2, 0.1),
+            Color::new(0.1, 0.6, 0.2),
+            Color::new(0.2, 0.3, 0.9),
+            Color::new(4.0, 3.0, 1.0),
+        ] {
+            let back = round_trip(rgb);
+            let scale = rgb.r.max(rgb.g).max(rgb.b);
+            for (a, b) in [(back.r, rgb.r), (back.g, rgb.g), (back.b, rgb.b)] {
+                assert!(
+                    (a - b).abs() < 0.08 * scale,
+                    "{:?} came back as {:?}",
+                    rgb,
+                    back
+                );
+            }
+        }
+    }
+}
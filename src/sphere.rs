@@ -1,17 +1,23 @@
+use crate::aabb::Aabb;
 use crate::hittable::HitRecord;
 use crate::hittable::Hittable;
 use crate::material::Material;
+use crate::onb::Onb;
 use crate::ray::Ray;
-use crate::vec3::Point3;
+use crate::sampler::Sampler;
+use crate::vec3::{Point3, Vec3};
+use std::f64::consts::PI;
+use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct Sphere {
     pub center: Point3,
     pub radius: f64,
-    pub material: Box<dyn Material>,
+    pub material: Arc<dyn Material>,
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let oc = r.origin - self.center;
         let a = r.direction.length_squared();
         let half_b = oc.dot(&r.direction);
@@ -26,14 +32,74 @@ impl Hittable for Sphere {
         if let Some(t) = find_root_in_range(a, half_b, t_min, t_max, sqrtd) {
             let p = r.at(t);
             let outward_normal = (p - self.center) / self.radius;
-            let rec = HitRecord::new(p, t, r, &outward_normal, &*self.material);
-            return Some(rec);
+            Some(HitRecord::new(
+                p,
+                t,
+                r,
+                &outward_normal,
+                sphere_uv(&outward_normal),
+                &*self.material,
+            ))
         } else {
+            None
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = self.radius.abs();
+        let extent = Vec3::new(r, r, r);
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+
+    // Uniform over the cone of directions the sphere subtends from `origin`.
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let cos_theta_max = match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => cos_theta_max,
+            None => return 0.0,
+        };
+        if self
+            .hit(&Ray::new(origin, direction), 0.001, f64::INFINITY)
+            .is_none()
+        {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let cos_theta_max = match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => cos_theta_max,
+            None => return self.center - origin,
+        };
+        let (r1, r2) = sampler.get_2d();
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = (1.0 - z * z).sqrt();
+        let local = Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z);
+        Onb::from_w(self.center - origin).local(local)
+    }
+}
+
+impl Sphere {
+    // Half angle of the cone subtended from `origin`, `None` from inside the sphere.
+    fn cos_theta_max(&self, origin: Point3) -> Option<f64> {
+        let distance_squared = (self.center - origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
             return None;
         }
+        Some((1.0 - radius_squared / distance_squared).sqrt())
     }
 }
 
+// Longitude and latitude of a point on the unit sphere, both mapped to [0, 1]. `u` runs from
+// -x through +z, `v` from the south pole to the north pole.
+fn sphere_uv(p: &Point3) -> (f64, f64) {
+    let theta = (-p.y).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z).atan2(p.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
 // Find the nearest root that lies in the acceptable range.
 fn find_root_in_range(a: f64, half_b: f64, t_min: f64, t_max: f64, sqrtd: f64) -> Option<f64> {
     let root = (-half_b - sqrtd) / a;
@@ -46,3 +112,22 @@ fn find_root_in_range(a: f64, half_b: f64, t_min: f64, t_max: f64, sqrtd: f64) -
     }
     Some(root)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spherical_uv() {
+        let uv = |x, y, z| {
+            let (u, v) = sphere_uv(&Point3::new(x, y, z));
+            ((u * 1e9).round() / 1e9, (v * 1e9).round() / 1e9)
+        };
+        assert_eq!(uv(1.0, 0.0, 0.0), (0.5, 0.5));
+        assert_eq!(uv(-1.0, 0.0, 0.0), (0.0, 0.5));
+        assert_eq!(uv(0.0, 1.0, 0.0).1, 1.0);
+        assert_eq!(uv(0.0, -1.0, 0.0).1, 0.0);
+        assert_eq!(uv(0.0, 0.0, 1.0), (0.25, 0.5));
+        assert_eq!(uv(0.0, 0.0, -1.0), (0.75, 0.5));
+    }
+}
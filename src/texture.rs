@@ -0,0 +1,185 @@
+use crate::color::Color;
+use crate::image::Framebuffer;
+use crate::perlin::Perlin;
+use crate::vec3::Point3;
+use std::sync::Arc;
+
+/// Color varying over a surface, looked up by texture coordinates and position.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SolidColor {
+    pub color: Color,
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.color
+    }
+}
+
+/// Alternates between two textures in cubes of side `scale`.
+pub struct CheckerTexture {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub scale: f64,
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let cell = |x: f64| (x / self.scale).floor() as i64;
+        if (cell(p.x) + cell(p.y) + cell(p.z)).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+/// How texture coordinates outside [0, 1] map onto an image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    fn apply(self, i: i64, n: u32) -> u32 {
+        let n = n as i64;
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Clamp => i.clamp(0, n - 1),
+            WrapMode::Mirror => {
+                let i = i.rem_euclid(2 * n);
+                if i < n {
+                    i
+                } else {
+                    2 * n - 1 - i
+                }
+            }
+        };
+        i as u32
+    }
+}
+
+/// Image mapped onto `[0, 1]²` with `v` pointing up, filtered bilinearly.
+pub struct ImageTexture {
+    pub image: Framebuffer,
+    pub wrap: WrapMode,
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        let (width, height) = (self.image.width, self.image.height);
+        // Texel centers sit at half integer coordinates.
+        let x = u * width as f64 - 0.5;
+        let y = (1.0 - v) * height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let texel = |i: i64, j: i64| {
+            self.image
+                .pixel(self.wrap.apply(i, width), self.wrap.apply(j, height))
+        };
+        (1.0 - fy) * ((1.0 - fx) * texel(x0, y0) + fx * texel(x0 + 1, y0))
+            + fy * ((1.0 - fx) * texel(x0, y0 + 1) + fx * texel(x0 + 1, y0 + 1))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NoiseKind {
+    /// Plain Perlin noise
+    Noise,
+    /// Several octaves of noise
+    Turbulence,
+    /// Stripes along z distorted by turbulence
+    Marble,
+}
+
+/// Grey Perlin noise with features of size about `1 / scale`.
+pub struct NoiseTexture {
+    pub noise: Perlin,
+    pub scale: f64,
+    pub kind: NoiseKind,
+}
+
+const TURBULENCE_DEPTH: usize = 7;
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let scaled = self.scale * *p;
+        let value = match self.kind {
+            NoiseKind::Noise => 0.5 * (1.0 + self.noise.noise(&scaled)),
+            NoiseKind::Turbulence => self.noise.turbulence(&scaled, TURBULENCE_DEPTH),
+            NoiseKind::Marble => {
+                let turbulence = self.noise.turbulence(p, TURBULENCE_DEPTH);
+                0.5 * (1.0 + (scaled.z + 10.0 * turbulence).sin())
+            }
+        };
+        Color::new(value, value, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(v: f64) -> Arc<dyn Texture> {
+        Arc::new(SolidColor {
+            color: Color::new(v, v, v),
+        })
+    }
+
+    #[test]
+    fn checker_alternates() {
+        let checker = CheckerTexture {
+            even: solid(1.0),
+            odd: solid(0.0),
+            scale: 2.0,
+        };
+        let at = |x, y, z| checker.value(0.0, 0.0, &Point3::new(x, y, z)).r;
+        assert_eq!(at(0.5, 0.5, 0.5), 1.0);
+        assert_eq!(at(2.5, 0.5, 0.5), 0.0);
+        assert_eq!(at(-0.5, 0.5, 0.5), 0.0);
+        assert_eq!(at(-0.5, -0.5, 0.5), 1.0);
+    }
+
+    #[test]
+    fn wrap_modes() {
+        let wrapped = |mode: WrapMode| [-4, -1, 0, 2, 3, 5].map(|i| mode.apply(i, 3));
+        assert_eq!(wrapped(WrapMode::Repeat), [2, 2, 0, 2, 0, 2]);
+        assert_eq!(wrapped(WrapMode::Clamp), [0, 0, 0, 2, 2, 2]);
+        assert_eq!(wrapped(WrapMode::Mirror), [2, 0, 0, 2, 2, 0]);
+    }
+
+    #[test]
+    fn image_filtered_bilinearly() {
+        // 2x1 image, black on the left and white on the right
+        let image = Framebuffer::from_pixels(
+            2,
+            1,
+            vec![Color::new(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0)],
+        );
+        let texture = ImageTexture {
+            image,
+            wrap: WrapMode::Clamp,
+        };
+        let at = |u| texture.value(u, 0.5, &Point3::new(0.0, 0.0, 0.0)).r;
+        assert_eq!(at(0.25), 0.0);
+        assert_eq!(at(0.5), 0.5);
+        assert_eq!(at(0.75), 1.0);
+        assert_eq!(at(1.5), 1.0);
+
+        let texture = ImageTexture {
+            wrap: WrapMode::Repeat,
+            ..texture
+        };
+        let at = |u| texture.value(u, 0.5, &Point3::new(0.0, 0.0, 0.0)).r;
+        // Halfway between the last and the first texel
+        assert_eq!(at(1.0), 0.5);
+        assert_eq!(at(1.25), 0.0);
+    }
+}
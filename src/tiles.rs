@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+
+/// Rectangle of pixels rendered as one unit of work, in film coordinates from the top left.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Order in which the tiles of a frame are handed out.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Left to right, top to bottom.
+    Row,
+    /// Outwards from the center, where the subject usually is.
+    Spiral,
+    /// Along a Hilbert curve, keeping consecutive tiles next to each other.
+    Hilbert,
+}
+
+/// Splits a `width` by `height` frame into tiles of at most `size` pixels square.
+pub fn tiles(width: u32, height: u32, size: u32, order: TileOrder) -> Vec<Tile> {
+    let columns = width.div_ceil(size);
+    let rows = height.div_ceil(size);
+    let tile = |(column, row): (u32, u32)| Tile {
+        x: column * size,
+        y: row * size,
+        width: size.min(width - column * size),
+        height: size.min(height - row * size),
+    };
+    let cells: Vec<(u32, u32)> = match order {
+        TileOrder::Row => (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .collect(),
+        TileOrder::Spiral => spiral(columns, rows),
+        TileOrder::Hilbert => {
+            let side = columns.max(rows).next_power_of_two();
+            (0..side as u64 * side as u64)
+                .map(|d| hilbert_cell(side, d))
+                .filter(|&(column, row)| column < columns && row < rows)
+                .collect()
+        }
+    };
+    cells.into_iter().map(tile).collect()
+}
+
+// Walks a square spiral out of the center cell, right, down, left, up with growing legs, until
+// every cell of the grid has been visited.
+fn spiral(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let total = columns as usize * rows as usize;
+    let mut cells = Vec::with_capacity(total);
+    let mut seen = HashSet::with_capacity(total);
+    let (mut x, mut y) = ((columns as i64 - 1) / 2, (rows as i64 - 1) / 2);
+    let mut visit = |x: i64, y: i64, cells: &mut Vec<(u32, u32)>| {
+        if x >= 0 && y >= 0 && x < columns as i64 && y < rows as i64 && seen.insert((x, y)) {
+            cells.push((x as u32, y as u32));
+        }
+    };
+    visit(x, y, &mut cells);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut leg = 1;
+    while cells.len() < total {
+        for (turn, (dx, dy)) in directions.iter().enumerate() {
+            for _ in 0..leg {
+                x += dx;
+                y += dy;
+                visit(x, y, &mut cells);
+            }
+            if turn % 2 == 1 {
+                leg += 1;
+            }
+        }
+    }
+    cells
+}
+
+// Cell at distance `d` along the Hilbert curve filling a `side` by `side` grid, `side` a power
+// of two.
+fn hilbert_cell(side: u32, d: u64) -> (u32, u32) {
+    let (mut x, mut y) = (0u64, 0u64);
+    let mut t = d;
+    let mut s = 1u64;
+    while s < side as u64 {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x as u32, y as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn covers_frame_once(width: u32, height: u32, tiles: &[Tile]) -> bool {
+        let mut hits = vec![0; width as usize * height as usize];
+        for tile in tiles {
+            for y in tile.y..tile.y + tile.height {
+                for x in tile.x..tile.x + tile.width {
+                    hits[y as usize * width as usize + x as usize] += 1;
+                }
+            }
+        }
+        hits.iter().all(|&count| count == 1)
+    }
+
+    #[test]
+    fn every_order_covers_the_frame_once() {
+        for order in [TileOrder::Row, TileOrder::Spiral, TileOrder::Hilbert] {
+            for (width, height) in [(100, 60), (33, 200), (7, 5), (64, 64)] {
+                let tiles = tiles(width, height, 16, order);
+                assert!(covers_frame_once(width, height, &tiles), "{:?}", order);
+            }
+        }
+    }
+
+    #[test]
+    fn spiral_starts_in_the_center() {
+        let tiles = tiles(100, 100, 20, TileOrder::Spiral);
+        assert_eq!((tiles[0].x, tiles[0].y), (40, 40));
+        assert_eq!((tiles[1].x, tiles[1].y), (60, 40));
+        assert_eq!((tiles[2].x, tiles[2].y), (60, 60));
+    }
+
+    #[test]
+    fn hilbert_steps_to_neighbours() {
+        let tiles = tiles(128, 128, 16, TileOrder::Hilbert);
+        assert_eq!(tiles.len(), 64);
+        for pair in tiles.windows(2) {
+            let dx = (pair[0].x as i64 - pair[1].x as i64).abs();
+            let dy = (pair[0].y as i64 - pair[1].y as i64).abs();
+            assert_eq!(dx + dy, 16);
+        }
+    }
+}
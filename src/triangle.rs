@@ -0,0 +1,316 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::{Point3, Vec3};
+use std::sync::Arc;
+
+/// Indexed triangles sharing vertex attributes and a single material.
+///
+/// `normals` and `uvs` are either empty or hold one entry per position. Faces are wound
+/// counter-clockwise when seen from the front.
+pub struct TriangleMesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub indices: Vec<[usize; 3]>,
+    pub material: Arc<dyn Material>,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        indices: Vec<[usize; 3]>,
+        material: Arc<dyn Material>,
+    ) -> Result<Self, String> {
+        let mesh = Self {
+            positions,
+            normals,
+            uvs,
+            indices,
+            material,
+        };
+        mesh.validate()?;
+        Ok(mesh)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let vertices = self.positions.len();
+        if !self.normals.is_empty() && self.normals.len() != vertices {
+            return Err(format!(
+                "mesh has {} normals for {} vertices",
+                self.normals.len(),
+                vertices
+            ));
+        }
+        if !self.uvs.is_empty() && self.uvs.len() != vertices {
+            return Err(format!(
+                "mesh has {} texture coordinates for {} vertices",
+                self.uvs.len(),
+                vertices
+            ));
+        }
+        match self.indices.iter().flatten().find(|&&i| i >= vertices) {
+            Some(i) => Err(format!(
+                "vertex index {} is out of range for {} vertices",
+                i, vertices
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// One hittable per face, to be put in a BVH.
+    pub fn triangles(self: Arc<Self>) -> Vec<Box<dyn Hittable>> {
+        (0..self.indices.len())
+            .map(|face| {
+                Box::new(Triangle {
+                    mesh: Arc::clone(&self),
+                    face,
+                }) as Box<dyn Hittable>
+            })
+            .collect()
+    }
+}
+
+pub struct Triangle {
+    mesh: Arc<TriangleMesh>,
+    face: usize,
+}
+
+impl Triangle {
+    fn vertices(&self) -> [Point3; 3] {
+        let [i0, i1, i2] = self.mesh.indices[self.face];
+        let positions = &self.mesh.positions;
+        [positions[i0], positions[i1], positions[i2]]
+    }
+}
+
+impl Hittable for Triangle {
+    // Watertight ray/triangle intersection, Woop, Benthin and Wald, JCGT 2013.
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let vertices = self.vertices();
+
+        // Make the largest direction component z so the shear below is well defined.
+        let d = r.direction;
+        let kz = (0..3)
+            .max_by(|&a, &b| d[a].abs().total_cmp(&d[b].abs()))
+            .unwrap();
+        let kx = (kz + 1) % 3;
+        let ky = (kx + 1) % 3;
+        let permute = |v: Vec3| Vec3::new(v[kx], v[ky], v[kz]);
+
+        // Transform into a space where the ray starts at the origin and points along +z.
+        let d = permute(d);
+        let shear_x = -d.x / d.z;
+        let shear_y = -d.y / d.z;
+        let shear_z = 1.0 / d.z;
+        let [p0, p1, p2] = vertices.map(|v| {
+            let p = permute(v - r.origin);
+            Vec3::new(p.x + shear_x * p.z, p.y + shear_y * p.z, p.z * shear_z)
+        });
+
+        // Edge functions, all of one sign inside the triangle.
+        let e0 = p1.x * p2.y - p1.y * p2.x;
+        let e1 = p2.x * p0.y - p2.y * p0.x;
+        let e2 = p0.x * p1.y - p0.y * p1.x;
+        if (e0 < 0.0 || e1 < 0.0 || e2 < 0.0) && (e0 > 0.0 || e1 > 0.0 || e2 > 0.0) {
+            return None;
+        }
+        let det = e0 + e1 + e2;
+        if det == 0.0 {
+            return None;
+        }
+
+        let t = (e0 * p0.z + e1 * p1.z + e2 * p2.z) / det;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        let barycentric = [e0 / det, e1 / det, e2 / det];
+        let interpolate =
+            |[a, b, c]: [Vec3; 3]| barycentric[0] * a + barycentric[1] * b + barycentric[2] * c;
+        let p = interpolate(vertices);
+        let [v0, v1, v2] = vertices;
+        let outward_normal = (v1 - v0).cross(v2 - v0).unit_vector();
+        let [i0, i1, i2] = self.mesh.indices[self.face];
+        let uv = if self.mesh.uvs.is_empty() {
+            (barycentric[1], barycentric[2])
+        } else {
+            let uvs = &self.mesh.uvs;
+            let [(u0, v0), (u1, v1), (u2, v2)] = [uvs[i0], uvs[i1], uvs[i2]];
+            (
+                barycentric[0] * u0 + barycentric[1] * u1 + barycentric[2] * u2,
+                barycentric[0] * v0 + barycentric[1] * v1 + barycentric[2] * v2,
+            )
+        };
+        let mut rec = HitRecord::new(p, t, r, &outward_normal, uv, &*self.mesh.material);
+
+        if !self.mesh.normals.is_empty() {
+            let normals = &self.mesh.normals;
+            let shading_normal = interpolate([normals[i0], normals[i1], normals[i2]]);
+            if !shading_normal.near_zero() {
+                // Keep the shading normal on the same side as the geometric one.
+                let shading_normal = shading_normal.unit_vector();
+                rec.normal = if shading_normal.dot(&rec.normal) < 0.0 {
+                    -shading_normal
+                } else {
+                    shading_normal
+                };
+            }
+        }
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [v0, v1, v2] = self.vertices();
+        Some(
+            Aabb::new(v0, v0)
+                .surrounding_point(v1)
+                .surrounding_point(v2),
+        )
+    }
+
+    // Uniform over the area, converted to solid angle at `origin`.
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let rec = match self.hit(&Ray::new(origin, direction), 0.001, f64::INFINITY) {
+            Some(rec) => rec,
+            None => return 0.0,
+        };
+        let [v0, v1, v2] = self.vertices();
+        let normal = (v1 - v0).cross(v2 - v0);
+        let area = 0.5 * normal.length();
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = direction.dot(&normal).abs() / (direction.length() * normal.length());
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let [v0, v1, v2] = self.vertices();
+        let (r1, r2) = sampler.get_2d();
+        let s = r1.sqrt();
+        let p = (1.0 - s) * v0 + s * (1.0 - r2) * v1 + s * r2 * v2;
+        p - origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    // Unit square in the z = 0 plane facing +z, split along its diagonal.
+    fn square(normals: Vec<Vec3>) -> Vec<Box<dyn Hittable>> {
+        let mesh = TriangleMesh::new(
+            vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(1.0, 1.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            normals,
+            Vec::new(),
+            vec![[0, 1, 2], [0, 2, 3]],
+            material(),
+        )
+        .unwrap();
+        Arc::new(mesh).triangles()
+    }
+
+    fn hits(triangles: &[Box<dyn Hittable>], r: &Ray) -> usize {
+        triangles
+            .iter()
+            .filter(|t| t.hit(r, 0.0, f64::INFINITY).is_some())
+            .count()
+    }
+
+    #[test]
+    fn hit_front_and_back() {
+        let triangles = square(Vec::new());
+        let r = Ray::new(Point3::new(0.75, 0.25, 2.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = triangles[0].hit(&r, 0.0, f64::INFINITY).unwrap();
+        assert_eq!(rec.t, 2.0);
+        assert_eq!(rec.p, Point3::new(0.75, 0.25, 0.0));
+        assert!(rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+
+        let r = Ray::new(Point3::new(0.75, 0.25, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = triangles[0].hit(&r, 0.0, f64::INFINITY).unwrap();
+        assert!(!rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, -1.0));
+
+        assert!(triangles[0].hit(&r, 0.0, 1.0).is_none());
+    }
+
+    #[test]
+    fn miss_outside() {
+        let triangles = square(Vec::new());
+        let r = Ray::new(Point3::new(1.5, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(hits(&triangles, &r), 0);
+        let parallel = Ray::new(Point3::new(-1.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(hits(&triangles, &parallel), 0);
+    }
+
+    #[test]
+    fn watertight_shared_edge() {
+        // Rays through the shared diagonal must never slip between the two triangles.
+        let triangles = square(Vec::new());
+        for i in 1..100 {
+            let target = Point3::new(i as f64 / 100.0, i as f64 / 100.0, 0.0);
+            let origin = target + i as f64 * Vec3::new(0.03, -0.02, 0.1);
+            let r = Ray::new(origin, target - origin);
+            assert!(hits(&triangles, &r) >= 1, "ray {:?} slipped through", r);
+        }
+    }
+
+    #[test]
+    fn smooth_normals_interpolate() {
+        let tilted = Vec3::new(1.0, 0.0, 1.0).unit_vector();
+        let up = Vec3::new(0.0, 0.0, 1.0);
+        let triangles = square(vec![up, tilted, tilted, up]);
+        let r = Ray::new(Point3::new(0.5, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = triangles[0].hit(&r, 0.0, f64::INFINITY).unwrap();
+        let expected = (0.5 * up + 0.5 * tilted).unit_vector();
+        assert!((rec.normal - expected).length() < 1e-12);
+    }
+
+    #[test]
+    fn interpolates_uvs() {
+        let mesh = TriangleMesh::new(
+            vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(2.0, 0.0, 0.0),
+                Point3::new(0.0, 2.0, 0.0),
+            ],
+            Vec::new(),
+            vec![(0.5, 0.5), (1.0, 0.5), (0.5, 1.0)],
+            vec![[0, 1, 2]],
+            material(),
+        )
+        .unwrap();
+        let triangles = Arc::new(mesh).triangles();
+        let r = Ray::new(Point3::new(0.5, 1.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = triangles[0].hit(&r, 0.0, f64::INFINITY).unwrap();
+        assert!((rec.u - 0.625).abs() < 1e-12 && (rec.v - 0.75).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rejects_out_of_range_index() {
+        let mesh = TriangleMesh::new(
+            vec![Point3::new(0.0, 0.0, 0.0)],
+            Vec::new(),
+            Vec::new(),
+            vec![[0, 0, 1]],
+            material(),
+        );
+        assert!(mesh.is_err());
+    }
+}
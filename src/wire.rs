@@ -0,0 +1,75 @@
+//! Little-endian encoding shared by checkpoints and the distributed rendering protocol.
+
+use std::io::{self, Read, Write};
+
+pub fn write_u8(out: &mut dyn Write, value: u8) -> io::Result<()> {
+    out.write_all(&[value])
+}
+
+pub fn write_u32(out: &mut dyn Write, value: u32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+pub fn write_u64(out: &mut dyn Write, value: u64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+pub fn write_f64(out: &mut dyn Write, value: f64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+/// Length-prefixed UTF-8.
+pub fn write_str(out: &mut dyn Write, value: &str) -> io::Result<()> {
+    write_u32(out, value.len() as u32)?;
+    out.write_all(value.as_bytes())
+}
+
+pub fn read_u8(input: &mut dyn Read) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+pub fn read_u32(input: &mut dyn Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub fn read_u64(input: &mut dyn Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub fn read_f64(input: &mut dyn Read) -> io::Result<f64> {
+    read_u64(input).map(f64::from_bits)
+}
+
+pub fn read_string(input: &mut dyn Read) -> io::Result<String> {
+    let mut bytes = vec![0; read_u32(input)? as usize];
+    input.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|e| invalid_data(e.to_string()))
+}
+
+/// Checks that `input` starts with `magic`, naming the expected data as `what` if not.
+pub fn expect_magic(input: &mut dyn Read, magic: &[u8; 8], what: &str) -> io::Result<()> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    if &bytes == magic {
+        Ok(())
+    } else {
+        Err(invalid_data(format!("not a {}", what)))
+    }
+}
+
+/// 64-bit FNV-1a hash, which unlike the hashers of the standard library is fixed for good.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+pub fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}